@@ -1,5 +1,4 @@
-// /src/alarms_handler.rs
-use defmt::debug;
+// /embassy800c-core/src/alarms_handler.rs
 use crate::constants::{ALARMS_CHANNELS_AMOUNT, ALARMS_MESSAGE_STRING_LENGTH, ALARMS_STACK_DEPTH};
 
 const FIRST_STACK_INDEX: usize = 0;
@@ -32,6 +31,8 @@ impl AlarmStack {
     }
 }
 
+// Row/column indexing mirrors the [row][col] layout of the alarm matrix.
+#[allow(clippy::needless_range_loop)]
 impl AlarmTracker for AlarmStack {
     fn push(&mut self, alarms: &[bool; ALARMS_CHANNELS_AMOUNT]) {
         if self.counter < ALARMS_STACK_DEPTH {
@@ -96,4 +97,50 @@ impl Default for AlarmStack {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steady_state_has_no_changes() {
+        let mut stack = AlarmStack::new();
+        for _ in 0..5 {
+            stack.push(&[true, false, false]);
+        }
+        assert!(!stack.has_changes());
+        assert_eq!(stack.export_bits(), ['7', '0', '0']);
+    }
+
+    #[test]
+    fn export_encodes_history_per_channel() {
+        let mut stack = AlarmStack::new();
+        stack.push(&[false, false, true]);
+        stack.push(&[true, false, true]);
+        stack.push(&[true, true, false]);
+        assert!(stack.has_changes());
+        // Row N of the history sets bit N of the channel digit.
+        assert_eq!(stack.export_bits(), ['6', '4', '3']);
+    }
+
+    #[test]
+    fn export_keeps_latest_state_as_baseline() {
+        let mut stack = AlarmStack::new();
+        stack.push(&[false, false, false]);
+        stack.push(&[true, false, false]);
+        stack.push(&[true, false, false]);
+        stack.export_bits();
+        assert_eq!(stack.get_stack_view()[0], [true, false, false]);
+    }
+
+    #[test]
+    fn import_round_trips_export() {
+        let mut stack = AlarmStack::new();
+        stack.import_bits(['5', '2', '7']);
+        assert_eq!(
+            stack.get_stack_view(),
+            [[true, false, true], [false, true, true], [true, false, true]]
+        );
+        assert_eq!(stack.export_bits(), ['5', '2', '7']);
+    }
+}
@@ -0,0 +1,839 @@
+// /embassy800c-core/src/at_parser.rs
+//! Classification of SIM800 output lines into typed responses.
+//!
+//! Lines are matched on their prefix only, so text such as an SMS body that
+//! merely mentions `+CLIP:` is never mistaken for a URC. Parameters are split
+//! on commas outside double quotes.
+
+use crate::gsm_time_converter::GsmTime;
+use crate::signal_quality::SignalQuality;
+
+/// Final result code terminating a command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FinalResult {
+    Ok,
+    Error,
+    CmeError(u16),
+    CmsError(u16),
+    NoCarrier,
+    Busy,
+    NoAnswer,
+    NoDialtone,
+}
+
+/// Network registration state reported by `+CREG`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegistrationStatus {
+    NotRegistered,
+    Home,
+    Searching,
+    Denied,
+    Unknown,
+    Roaming,
+}
+
+impl RegistrationStatus {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0 => Self::NotRegistered,
+            1 => Self::Home,
+            2 => Self::Searching,
+            3 => Self::Denied,
+            5 => Self::Roaming,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Registered with the home network or roaming.
+    pub fn is_registered(&self) -> bool {
+        matches!(self, Self::Home | Self::Roaming)
+    }
+}
+
+/// SIM state reported by `+CPIN`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SimStatus {
+    Ready,
+    PinRequired,
+    PukRequired,
+    NotInserted,
+    Other,
+}
+
+impl SimStatus {
+    pub fn from_text(text: &str) -> Self {
+        match text {
+            "READY" => Self::Ready,
+            "SIM PIN" => Self::PinRequired,
+            "SIM PUK" => Self::PukRequired,
+            "NOT INSERTED" => Self::NotInserted,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Unsolicited result code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Urc<'a> {
+    Ring,
+    /// `+CMT: "<oa>",[<alpha>],"<scts>"`; the message text follows on the next line.
+    Cmt {
+        sender: &'a str,
+        alpha: Option<&'a str>,
+        timestamp: &'a str,
+    },
+    /// `+CMT: [<alpha>],<length>` in PDU mode; the hex PDU follows on the next line.
+    CmtPdu {
+        length: u8,
+    },
+    /// `+CDS: <fo>,<mr>,[<ra>],[<tora>],<scts>,<dt>,<st>`, an SMS status report.
+    Cds {
+        reference: u8,
+        status: u8,
+    },
+    /// `+CDS: <length>` in PDU mode; the hex PDU follows on the next line.
+    CdsPdu {
+        length: u8,
+    },
+    /// `+CMTI: <mem>,<index>`, an SMS was stored instead of routed.
+    Cmti {
+        index: u8,
+    },
+    /// `+CLIP: "<number>",<type>,"<subaddr>",<satype>,"<alpha>",<validity>`
+    Clip {
+        number: &'a str,
+        number_type: Option<u8>,
+        alpha: Option<&'a str>,
+        validity: Option<u8>,
+    },
+    /// `+DTMF: <key>`
+    Dtmf(char),
+    /// `+CREG: [<n>,]<stat>[,"<lac>","<ci>"]`
+    Creg {
+        status: RegistrationStatus,
+        lac: Option<&'a str>,
+        ci: Option<&'a str>,
+    },
+    /// Module booted (only reported at a fixed baud rate).
+    Rdy,
+    /// `+CFUN: <fun>`, also the reply to `AT+CFUN?`.
+    Cfun(u8),
+    /// `+CPIN: <code>`, also the reply to `AT+CPIN?`.
+    Cpin(SimStatus),
+    CallReady,
+    SmsReady,
+    /// Reply to `AT+CPOWD=1`, the module switches off right after it.
+    NormalPowerDown,
+    /// `+CUSD: <n>[,"<str>",<dcs>]`, the network's answer to a USSD request.
+    Cusd {
+        status: u8,
+        text: Option<&'a str>,
+        dcs: Option<u8>,
+    },
+}
+
+/// Information line answering a command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Info<'a> {
+    /// `+CPBR: <index>,"<number>",<type>,"<text>"`
+    Cpbr {
+        index: u8,
+        number: &'a str,
+        number_type: Option<u8>,
+        text: &'a str,
+    },
+    /// `+CMGS: <mr>`
+    Cmgs { reference: u8 },
+    /// `+CCLK: "<time>"`
+    Cclk(GsmTime),
+    /// `+CCALR: <mode>`, whether calls can be made.
+    Ccalr(bool),
+    /// `+SPIC: <pin1>,<pin2>,<puk1>,<puk2>`, remaining SIM unlock attempts.
+    Spic {
+        pin1: u8,
+        pin2: u8,
+        puk1: u8,
+        puk2: u8,
+    },
+    /// `+CSQ: <rssi>,<ber>`
+    Csq(SignalQuality),
+    /// `+CBC: <bcs>,<bcl>,<voltage>`, the supply voltage in mV.
+    Cbc {
+        charging: u8,
+        percent: u8,
+        millivolts: u16,
+    },
+    /// `+IPR: <rate>`, the fixed UART rate; 0 while autobauding.
+    Ipr(u32),
+    /// `+CPMS: <mem1>,<used1>,<total1>,...`, usage of the SMS read storage.
+    Cpms { used: u8, total: u8 },
+    /// `+CMGL: <index>,<stat>,<oa>,[<alpha>],[<scts>]`, or
+    /// `+CMGL: <index>,<stat>,[<alpha>],<length>` in PDU mode where the sender
+    /// is part of the PDU. The text or PDU follows on the next line.
+    Cmgl {
+        index: u8,
+        unread: bool,
+        sender: &'a str,
+    },
+}
+
+/// One line received from the modem.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Response<'a> {
+    Empty,
+    /// `>` text entry prompt after `AT+CMGS`.
+    Prompt,
+    Final(FinalResult),
+    Urc(Urc<'a>),
+    Info(Info<'a>),
+    /// Anything else: command echo, SMS text, unknown responses.
+    Text(&'a str),
+}
+
+/// Classifies a single line (without CR/LF) received from the modem.
+pub fn parse_line(line: &str) -> Response<'_> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Response::Empty;
+    }
+    if line == ">" {
+        return Response::Prompt;
+    }
+
+    if let Some(result) = parse_final(line) {
+        return Response::Final(result);
+    }
+    let urc = match line {
+        "RING" => Some(Urc::Ring),
+        "RDY" => Some(Urc::Rdy),
+        "Call Ready" => Some(Urc::CallReady),
+        "SMS Ready" => Some(Urc::SmsReady),
+        "NORMAL POWER DOWN" => Some(Urc::NormalPowerDown),
+        _ => None,
+    };
+    if let Some(urc) = urc {
+        return Response::Urc(urc);
+    }
+
+    let Some((name, params)) = split_prefixed(line) else {
+        return Response::Text(line);
+    };
+    let mut p = Params::new(params);
+
+    let parsed = match name {
+        "+CMT" => parse_cmt(&mut p).map(Response::Urc),
+        "+CLIP" => parse_clip(&mut p).map(Response::Urc),
+        "+CDS" => parse_cds(&mut p).map(Response::Urc),
+        "+CMTI" => p
+            .nth(1)
+            .and_then(|index| index.value.parse().ok())
+            .map(|index| Response::Urc(Urc::Cmti { index })),
+        "+DTMF" => p
+            .next()
+            .and_then(|key| key.value.chars().next())
+            .map(|key| Response::Urc(Urc::Dtmf(key))),
+        "+CREG" => parse_creg(&mut p).map(Response::Urc),
+        "+CUSD" => parse_cusd(&mut p).map(Response::Urc),
+        "+CPBR" => parse_cpbr(&mut p).map(Response::Info),
+        "+CMGS" => p
+            .next_u8()
+            .map(|reference| Response::Info(Info::Cmgs { reference })),
+        "+CMGL" => parse_cmgl(&mut p).map(Response::Info),
+        "+CPMS" => p
+            .nth(1)
+            .and_then(|used| used.value.parse().ok())
+            .zip(p.next_u8())
+            .map(|(used, total)| Response::Info(Info::Cpms { used, total })),
+        "+CCLK" => GsmTime::parse_cclk(line).map(|time| Response::Info(Info::Cclk(time))),
+        "+CFUN" => p.next_u8().map(|fun| Response::Urc(Urc::Cfun(fun))),
+        "+CPIN" => p
+            .next()
+            .map(|code| Response::Urc(Urc::Cpin(SimStatus::from_text(code.value)))),
+        "+CCALR" => p
+            .next_u8()
+            .map(|mode| Response::Info(Info::Ccalr(mode == 1))),
+        "+SPIC" => parse_spic(&mut p).map(Response::Info),
+        "+CBC" => parse_cbc(&mut p).map(Response::Info),
+        "+CSQ" => p
+            .next_u8()
+            .zip(p.next_u8())
+            .map(|(rssi, ber)| Response::Info(Info::Csq(SignalQuality { rssi, ber }))),
+        "+IPR" => p
+            .next()
+            .and_then(|rate| rate.value.parse().ok())
+            .map(|rate| Response::Info(Info::Ipr(rate))),
+        _ => None,
+    };
+    parsed.unwrap_or(Response::Text(line))
+}
+
+fn parse_final(line: &str) -> Option<FinalResult> {
+    let result = match line {
+        "OK" => FinalResult::Ok,
+        "ERROR" => FinalResult::Error,
+        "NO CARRIER" => FinalResult::NoCarrier,
+        "BUSY" => FinalResult::Busy,
+        "NO ANSWER" => FinalResult::NoAnswer,
+        "NO DIALTONE" => FinalResult::NoDialtone,
+        _ => {
+            // Verbose (`AT+CMEE=2`) error texts carry no code; report them as plain errors.
+            if let Some(code) = line.strip_prefix("+CME ERROR:") {
+                return Some(
+                    code.trim()
+                        .parse()
+                        .map_or(FinalResult::Error, FinalResult::CmeError),
+                );
+            }
+            if let Some(code) = line.strip_prefix("+CMS ERROR:") {
+                return Some(
+                    code.trim()
+                        .parse()
+                        .map_or(FinalResult::Error, FinalResult::CmsError),
+                );
+            }
+            return None;
+        }
+    };
+    Some(result)
+}
+
+/// Splits `+NAME: params` into `("+NAME", "params")`.
+fn split_prefixed(line: &str) -> Option<(&str, &str)> {
+    if !line.starts_with('+') {
+        return None;
+    }
+    let (name, params) = line.split_once(':')?;
+    Some((name, params.trim_start()))
+}
+
+fn parse_cmt<'a>(p: &mut Params<'a>) -> Option<Urc<'a>> {
+    let sender = p.next()?.value;
+    let alpha = p.next();
+    let timestamp = p.next();
+    if let (Some(length @ Param { quoted: false, .. }), None) = (alpha, timestamp) {
+        return length
+            .value
+            .parse()
+            .ok()
+            .map(|length| Urc::CmtPdu { length });
+    }
+    let alpha = alpha.map(|a| a.value).filter(|a| !a.is_empty());
+    let timestamp = timestamp.map_or("", |t| t.value);
+    Some(Urc::Cmt {
+        sender,
+        alpha,
+        timestamp,
+    })
+}
+
+fn parse_cds<'a>(p: &mut Params<'a>) -> Option<Urc<'a>> {
+    let first = p.next_u8()?;
+    let Some(reference) = p.next() else {
+        return Some(Urc::CdsPdu { length: first });
+    };
+    let reference = reference.value.parse().ok()?;
+    let status = p.nth(4)?.value.parse().ok()?;
+    Some(Urc::Cds { reference, status })
+}
+
+fn parse_cmgl<'a>(p: &mut Params<'a>) -> Option<Info<'a>> {
+    let index = p.next_u8()?;
+    let stat = p.next()?;
+    let unread = if stat.quoted {
+        stat.value == "REC UNREAD"
+    } else {
+        stat.value == "0"
+    };
+    let sender = if stat.quoted {
+        p.next().map_or("", |s| s.value)
+    } else {
+        ""
+    };
+    Some(Info::Cmgl {
+        index,
+        unread,
+        sender,
+    })
+}
+
+fn parse_clip<'a>(p: &mut Params<'a>) -> Option<Urc<'a>> {
+    let number = p.next()?.value;
+    let number_type = p.next_u8();
+    let _subaddr = p.next();
+    let _satype = p.next();
+    let alpha = p.next().map(|a| a.value).filter(|a| !a.is_empty());
+    let validity = p.next_u8();
+    Some(Urc::Clip {
+        number,
+        number_type,
+        alpha,
+        validity,
+    })
+}
+
+fn parse_creg<'a>(p: &mut Params<'a>) -> Option<Urc<'a>> {
+    let first = p.next()?.value.parse::<u8>().ok()?;
+    let second = p.next();
+    // `+CREG: <stat>,"<lac>","<ci>"` (URC with location) vs `+CREG: <n>,<stat>[,...]` (query reply).
+    let (code, lac) = match second {
+        None => (first, None),
+        Some(param) if param.quoted => (first, Some(param.value)),
+        Some(param) => (param.value.parse().ok()?, p.next().map(|l| l.value)),
+    };
+    let ci = p.next().map(|c| c.value);
+    Some(Urc::Creg {
+        status: RegistrationStatus::from_code(code),
+        lac,
+        ci,
+    })
+}
+
+fn parse_cusd<'a>(p: &mut Params<'a>) -> Option<Urc<'a>> {
+    let status = p.next_u8()?;
+    let text = p.next().map(|t| t.value);
+    let dcs = p.next_u8();
+    Some(Urc::Cusd { status, text, dcs })
+}
+
+fn parse_spic<'a>(p: &mut Params<'a>) -> Option<Info<'a>> {
+    Some(Info::Spic {
+        pin1: p.next_u8()?,
+        pin2: p.next_u8()?,
+        puk1: p.next_u8()?,
+        puk2: p.next_u8()?,
+    })
+}
+
+fn parse_cbc<'a>(p: &mut Params<'a>) -> Option<Info<'a>> {
+    let charging = p.next_u8()?;
+    let percent = p.next_u8()?;
+    let millivolts = p.next()?.value.parse().ok()?;
+    Some(Info::Cbc {
+        charging,
+        percent,
+        millivolts,
+    })
+}
+
+fn parse_cpbr<'a>(p: &mut Params<'a>) -> Option<Info<'a>> {
+    let index = p.next_u8()?;
+    let number = p.next()?.value;
+    let number_type = p.next_u8();
+    let text = p.next().map_or("", |t| t.value);
+    Some(Info::Cpbr {
+        index,
+        number,
+        number_type,
+        text,
+    })
+}
+
+/// A single command parameter, with surrounding quotes removed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Param<'a> {
+    pub value: &'a str,
+    pub quoted: bool,
+}
+
+/// Iterator over comma separated parameters; commas inside quotes are kept.
+pub struct Params<'a> {
+    rest: Option<&'a str>,
+}
+
+impl<'a> Params<'a> {
+    pub fn new(params: &'a str) -> Self {
+        Self { rest: Some(params) }
+    }
+
+    pub fn next_u8(&mut self) -> Option<u8> {
+        self.next()?.value.parse().ok()
+    }
+}
+
+impl<'a> Iterator for Params<'a> {
+    type Item = Param<'a>;
+
+    fn next(&mut self) -> Option<Param<'a>> {
+        let rest = self.rest?.trim_start();
+
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let (value, after) = match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            };
+            self.rest = after.split_once(',').map(|(_, tail)| tail);
+            return Some(Param {
+                value,
+                quoted: true,
+            });
+        }
+
+        let (value, tail) = match rest.split_once(',') {
+            Some((value, tail)) => (value, Some(tail)),
+            None => (rest, None),
+        };
+        self.rest = tail;
+        Some(Param {
+            value: value.trim_end(),
+            quoted: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn final_results() {
+        assert_eq!(parse_line("OK"), Response::Final(FinalResult::Ok));
+        assert_eq!(parse_line(" ERROR "), Response::Final(FinalResult::Error));
+        assert_eq!(
+            parse_line("+CME ERROR: 10"),
+            Response::Final(FinalResult::CmeError(10))
+        );
+        assert_eq!(
+            parse_line("+CMS ERROR: 304"),
+            Response::Final(FinalResult::CmsError(304))
+        );
+        assert_eq!(
+            parse_line("+CME ERROR: SIM not inserted"),
+            Response::Final(FinalResult::Error)
+        );
+        assert_eq!(
+            parse_line("NO CARRIER"),
+            Response::Final(FinalResult::NoCarrier)
+        );
+        assert_eq!(parse_line("BUSY"), Response::Final(FinalResult::Busy));
+        assert_eq!(
+            parse_line("NO ANSWER"),
+            Response::Final(FinalResult::NoAnswer)
+        );
+        assert_eq!(parse_line(""), Response::Empty);
+        assert_eq!(parse_line("> "), Response::Prompt);
+    }
+
+    #[test]
+    fn cmt_with_quoted_comma_and_alphanumeric_sender() {
+        assert_eq!(
+            parse_line(r#"+CMT: "+79001234567","","26/01/09,23:15:31+12""#),
+            Response::Urc(Urc::Cmt {
+                sender: "+79001234567",
+                alpha: None,
+                timestamp: "26/01/09,23:15:31+12",
+            })
+        );
+        assert_eq!(
+            parse_line(r#"+CMT: "Bee, Line","Operator","26/01/09,23:15:31+12""#),
+            Response::Urc(Urc::Cmt {
+                sender: "Bee, Line",
+                alpha: Some("Operator"),
+                timestamp: "26/01/09,23:15:31+12",
+            })
+        );
+        assert_eq!(
+            parse_line("+CMT: ,26"),
+            Response::Urc(Urc::CmtPdu { length: 26 })
+        );
+        assert_eq!(
+            parse_line(r#"+CMT: "Admin",26"#),
+            Response::Urc(Urc::CmtPdu { length: 26 })
+        );
+    }
+
+    #[test]
+    fn status_reports() {
+        assert_eq!(
+            parse_line(
+                r#"+CDS: 6,46,"+79001234567",145,"26/01/09,23:15:31+12","26/01/09,23:15:35+12",0"#
+            ),
+            Response::Urc(Urc::Cds {
+                reference: 46,
+                status: 0
+            })
+        );
+        assert_eq!(
+            parse_line(r#"+CDS: 6,47,,,"26/01/09,23:15:31+12","26/01/09,23:25:31+12",70"#),
+            Response::Urc(Urc::Cds {
+                reference: 47,
+                status: 70
+            })
+        );
+        assert_eq!(
+            parse_line("+CDS: 25"),
+            Response::Urc(Urc::CdsPdu { length: 25 })
+        );
+    }
+
+    #[test]
+    fn stored_messages() {
+        assert_eq!(
+            parse_line(r#"+CMTI: "SM",3"#),
+            Response::Urc(Urc::Cmti { index: 3 })
+        );
+        assert_eq!(
+            parse_line(r#"+CPMS: "SM",50,50,"SM",50,50,"SM",50,50"#),
+            Response::Info(Info::Cpms {
+                used: 50,
+                total: 50
+            })
+        );
+        assert_eq!(
+            parse_line(r#"+CMGL: 1,"REC UNREAD","+79001234567","","26/01/09,23:15:31+12""#),
+            Response::Info(Info::Cmgl {
+                index: 1,
+                unread: true,
+                sender: "+79001234567"
+            })
+        );
+        assert_eq!(
+            parse_line(r#"+CMGL: 2,"REC READ","+79001234567","","26/01/09,23:15:31+12""#),
+            Response::Info(Info::Cmgl {
+                index: 2,
+                unread: false,
+                sender: "+79001234567"
+            })
+        );
+        assert_eq!(
+            parse_line("+CMGL: 4,0,,24"),
+            Response::Info(Info::Cmgl {
+                index: 4,
+                unread: true,
+                sender: ""
+            })
+        );
+        assert_eq!(
+            parse_line("+CMGL: 5,1,,24"),
+            Response::Info(Info::Cmgl {
+                index: 5,
+                unread: false,
+                sender: ""
+            })
+        );
+    }
+
+    #[test]
+    fn clip_fields() {
+        assert_eq!(
+            parse_line(r#"+CLIP: "+79007654321",145,"",0,"Admin",0"#),
+            Response::Urc(Urc::Clip {
+                number: "+79007654321",
+                number_type: Some(145),
+                alpha: Some("Admin"),
+                validity: Some(0),
+            })
+        );
+        assert_eq!(
+            parse_line(r#"+CLIP: "",128"#),
+            Response::Urc(Urc::Clip {
+                number: "",
+                number_type: Some(128),
+                alpha: None,
+                validity: None
+            })
+        );
+    }
+
+    #[test]
+    fn urcs_are_matched_on_prefix_only() {
+        assert_eq!(
+            parse_line("PPP;+CLIP: 123"),
+            Response::Text("PPP;+CLIP: 123")
+        );
+        assert_eq!(parse_line("AT+CLIP=1"), Response::Text("AT+CLIP=1"));
+        assert_eq!(parse_line("RING"), Response::Urc(Urc::Ring));
+        assert_eq!(parse_line("+DTMF: #"), Response::Urc(Urc::Dtmf('#')));
+        assert_eq!(parse_line("+DTMF:5"), Response::Urc(Urc::Dtmf('5')));
+        assert_eq!(parse_line("+XYZ: 1"), Response::Text("+XYZ: 1"));
+    }
+
+    #[test]
+    fn creg_forms() {
+        assert_eq!(
+            parse_line("+CREG: 5"),
+            Response::Urc(Urc::Creg {
+                status: RegistrationStatus::Roaming,
+                lac: None,
+                ci: None
+            })
+        );
+        assert_eq!(
+            parse_line("+CREG: 0,1"),
+            Response::Urc(Urc::Creg {
+                status: RegistrationStatus::Home,
+                lac: None,
+                ci: None
+            })
+        );
+        assert_eq!(
+            parse_line(r#"+CREG: 1,"00A1","1B2C""#),
+            Response::Urc(Urc::Creg {
+                status: RegistrationStatus::Home,
+                lac: Some("00A1"),
+                ci: Some("1B2C")
+            })
+        );
+        assert_eq!(
+            parse_line(r#"+CREG: 2,3,"00A1","1B2C""#),
+            Response::Urc(Urc::Creg {
+                status: RegistrationStatus::Denied,
+                lac: Some("00A1"),
+                ci: Some("1B2C")
+            })
+        );
+    }
+
+    #[test]
+    fn information_lines() {
+        assert_eq!(
+            parse_line(r#"+CPBR: 2,"*105#",129,"Balance, main""#),
+            Response::Info(Info::Cpbr {
+                index: 2,
+                number: "*105#",
+                number_type: Some(129),
+                text: "Balance, main"
+            })
+        );
+        assert_eq!(
+            parse_line("+CMGS: 17"),
+            Response::Info(Info::Cmgs { reference: 17 })
+        );
+        assert_eq!(
+            parse_line(r#"+CCLK: "26/01/09,23:15:31+12""#),
+            Response::Info(Info::Cclk(GsmTime {
+                year: 26,
+                month: 1,
+                day: 9,
+                hour: 23,
+                minute: 15,
+                second: 31
+            }))
+        );
+        assert_eq!(
+            parse_line(r#"+CCLK: "bogus""#),
+            Response::Text(r#"+CCLK: "bogus""#)
+        );
+        assert_eq!(
+            parse_line("+CSQ: 20,0"),
+            Response::Info(Info::Csq(SignalQuality { rssi: 20, ber: 0 }))
+        );
+        assert_eq!(
+            parse_line("+CBC: 0,75,3950"),
+            Response::Info(Info::Cbc {
+                charging: 0,
+                percent: 75,
+                millivolts: 3950
+            })
+        );
+        assert_eq!(
+            parse_line("+CSQ: 99,99"),
+            Response::Info(Info::Csq(SignalQuality { rssi: 99, ber: 99 }))
+        );
+    }
+
+    #[test]
+    fn cusd_forms() {
+        assert_eq!(
+            parse_line(r#"+CUSD: 0,"Balance: 12.50 rub, tariff 7",15"#),
+            Response::Urc(Urc::Cusd {
+                status: 0,
+                text: Some("Balance: 12.50 rub, tariff 7"),
+                dcs: Some(15)
+            })
+        );
+        assert_eq!(
+            parse_line(r#"+CUSD: 2,"0411043000200031",72"#),
+            Response::Urc(Urc::Cusd {
+                status: 2,
+                text: Some("0411043000200031"),
+                dcs: Some(72)
+            })
+        );
+        assert_eq!(
+            parse_line("+CUSD: 4"),
+            Response::Urc(Urc::Cusd {
+                status: 4,
+                text: None,
+                dcs: None
+            })
+        );
+    }
+
+    #[test]
+    fn params_handle_quotes_and_blanks() {
+        let values: heapless::Vec<_, 8> = Params::new(r#" 1 , "a,b" ,,"c""#).collect();
+        assert_eq!(
+            values.as_slice(),
+            [
+                Param {
+                    value: "1",
+                    quoted: false
+                },
+                Param {
+                    value: "a,b",
+                    quoted: true
+                },
+                Param {
+                    value: "",
+                    quoted: false
+                },
+                Param {
+                    value: "c",
+                    quoted: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn readiness_urcs() {
+        assert_eq!(parse_line("RDY"), Response::Urc(Urc::Rdy));
+        assert_eq!(parse_line("+CFUN: 1"), Response::Urc(Urc::Cfun(1)));
+        assert_eq!(
+            parse_line("+CPIN: READY"),
+            Response::Urc(Urc::Cpin(SimStatus::Ready))
+        );
+        assert_eq!(
+            parse_line("+CPIN: SIM PIN"),
+            Response::Urc(Urc::Cpin(SimStatus::PinRequired))
+        );
+        assert_eq!(
+            parse_line("+CPIN: NOT INSERTED"),
+            Response::Urc(Urc::Cpin(SimStatus::NotInserted))
+        );
+        assert_eq!(parse_line("Call Ready"), Response::Urc(Urc::CallReady));
+        assert_eq!(parse_line("SMS Ready"), Response::Urc(Urc::SmsReady));
+        assert_eq!(
+            parse_line("NORMAL POWER DOWN"),
+            Response::Urc(Urc::NormalPowerDown)
+        );
+        assert_eq!(parse_line("+CCALR: 1"), Response::Info(Info::Ccalr(true)));
+        assert_eq!(parse_line("+CCALR: 0"), Response::Info(Info::Ccalr(false)));
+        assert_eq!(
+            parse_line("+SPIC: 3,3,10,10"),
+            Response::Info(Info::Spic {
+                pin1: 3,
+                pin2: 3,
+                puk1: 10,
+                puk2: 10
+            })
+        );
+        assert_eq!(parse_line("+SPIC: 3"), Response::Text("+SPIC: 3"));
+    }
+
+    #[test]
+    fn baud_rate() {
+        assert_eq!(
+            parse_line("+IPR: 115200"),
+            Response::Info(Info::Ipr(115200))
+        );
+        assert_eq!(parse_line("+IPR: 0"), Response::Info(Info::Ipr(0)));
+        assert_eq!(parse_line("+IPR: fast"), Response::Text("+IPR: fast"));
+    }
+}
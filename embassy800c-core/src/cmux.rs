@@ -0,0 +1,627 @@
+// /embassy800c-core/src/cmux.rs
+//! GSM 07.10 (3GPP TS 27.010) multiplexer in front of the SIM800 UART.
+//!
+//! Once the module accepted `AT+CMUX=0` it speaks basic-option frames and
+//! the UART carries independent virtual channels (DLCIs): the AT channel of
+//! the primary `Sim800Driver` and an auxiliary AT channel for the
+//! `QueryChannel`, usable while the first one is busy with a call. Before that,
+//! and again after the module was switched off, the link is plain and
+//! `CMUX_AT_DLCI` passes bytes through unchanged.
+
+use core::cell::Cell;
+
+use embassy_futures::select::{select3, Either3};
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::pipe::Pipe;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+use crate::constants::{
+    CMUX_CHANNELS, CMUX_CHANNEL_BUFFER_SIZE, CMUX_FRAME_SIZE, CMUX_OPEN_RETRIES, CMUX_OPEN_TIMEOUT_MS,
+    SIM800_RX_CHUNK_SIZE,
+};
+
+/// Multiplexer control channel.
+pub const CMUX_CONTROL_DLCI: u8 = 0;
+/// AT channel of the primary driver, and the whole link while it is plain.
+pub const CMUX_AT_DLCI: u8 = 1;
+/// Second AT channel, usable while the primary one is busy.
+pub const CMUX_AUX_DLCI: u8 = 2;
+
+const FLAG: u8 = 0xF9;
+/// Extension bit, set on the last octet of the address and length fields.
+const EA: u8 = 0x01;
+/// Command/response bit of the address and of control channel messages.
+const CR: u8 = 0x02;
+/// Poll/final bit of the control field.
+const PF: u8 = 0x10;
+/// Modem status command on the control channel.
+const MSC: u8 = 0xE1;
+/// Multiplexer close down command on the control channel.
+const CLD: u8 = 0xC1;
+/// V.24 signals sent with MSC: ready to communicate and to receive.
+const MSC_SIGNALS: u8 = 0x0D;
+
+/// Room for the header, the longest information field and the FCS.
+const MAX_ENCODED_FRAME: usize = CMUX_FRAME_SIZE + 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameKind {
+    /// Set asynchronous balanced mode: opens a channel.
+    Sabm,
+    /// Unnumbered acknowledgement.
+    Ua,
+    /// Disconnected mode: the channel is refused or closed.
+    Dm,
+    /// Disconnect: closes a channel.
+    Disc,
+    /// Unnumbered information with header check, carrying the data.
+    Uih,
+}
+
+impl FrameKind {
+    fn control(self) -> u8 {
+        match self {
+            FrameKind::Sabm => 0x2F,
+            FrameKind::Ua => 0x63,
+            FrameKind::Dm => 0x0F,
+            FrameKind::Disc => 0x43,
+            FrameKind::Uih => 0xEF,
+        }
+    }
+
+    fn from_control(control: u8) -> Option<Self> {
+        [FrameKind::Sabm, FrameKind::Ua, FrameKind::Dm, FrameKind::Disc, FrameKind::Uih]
+            .into_iter()
+            .find(|kind| kind.control() == control & !PF)
+    }
+
+    /// Frames the multiplexer initiator sends as commands (C/R set).
+    fn is_command(self) -> bool {
+        matches!(self, FrameKind::Sabm | FrameKind::Disc | FrameKind::Uih)
+    }
+}
+
+/// One decoded frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame<'a> {
+    pub dlci: u8,
+    pub kind: FrameKind,
+    pub info: &'a [u8],
+}
+
+/// Frame check sequence over the header octets (CRC-8, polynomial
+/// x^8 + x^2 + x + 1, reflected).
+fn fcs(header: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+    for &b in header {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xE0 } else { crc >> 1 };
+        }
+    }
+    0xFF - crc
+}
+
+/// Encodes a frame as sent by the initiator into `out`, returning its length.
+///
+/// `info` must not exceed `CMUX_FRAME_SIZE` and `out` must hold
+/// `info.len() + 6` bytes.
+pub fn encode_frame(dlci: u8, kind: FrameKind, info: &[u8], out: &mut [u8]) -> usize {
+    let cr = if kind.is_command() { CR } else { 0 };
+    let pf = if kind == FrameKind::Uih { 0 } else { PF };
+    let header = [(dlci << 2) | cr | EA, kind.control() | pf, ((info.len() as u8) << 1) | EA];
+
+    out[0] = FLAG;
+    out[1..4].copy_from_slice(&header);
+    out[4..4 + info.len()].copy_from_slice(info);
+    out[4 + info.len()] = fcs(&header);
+    out[5 + info.len()] = FLAG;
+    info.len() + 6
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DecodeState {
+    Flag,
+    Address,
+    Control,
+    Length,
+    Length2,
+    Info,
+    Fcs,
+    End,
+}
+
+/// Byte-wise frame decoder; frames with a bad FCS or an oversized
+/// information field are dropped and the decoder waits for the next flag.
+pub struct FrameDecoder {
+    state: DecodeState,
+    header: [u8; 4],
+    header_len: usize,
+    len: usize,
+    pos: usize,
+    info: [u8; CMUX_FRAME_SIZE],
+}
+
+impl FrameDecoder {
+    pub const fn new() -> Self {
+        Self {
+            state: DecodeState::Flag,
+            header: [0; 4],
+            header_len: 0,
+            len: 0,
+            pos: 0,
+            info: [0; CMUX_FRAME_SIZE],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = DecodeState::Flag;
+    }
+
+    /// Feeds one received byte, returning the frame it completed.
+    pub fn push(&mut self, b: u8) -> Option<Frame<'_>> {
+        match self.state {
+            DecodeState::Flag => {
+                if b == FLAG {
+                    self.state = DecodeState::Address;
+                }
+            }
+            // Repeated flags between frames are allowed.
+            DecodeState::Address if b == FLAG => {}
+            DecodeState::Address => {
+                self.header[0] = b;
+                self.state = if b & EA != 0 { DecodeState::Control } else { DecodeState::Flag };
+            }
+            DecodeState::Control => {
+                self.header[1] = b;
+                self.state = DecodeState::Length;
+            }
+            DecodeState::Length => {
+                self.header[2] = b;
+                self.header_len = 3;
+                self.len = (b >> 1) as usize;
+                if b & EA == 0 {
+                    self.state = DecodeState::Length2;
+                } else {
+                    self.start_info();
+                }
+            }
+            DecodeState::Length2 => {
+                self.header[3] = b;
+                self.header_len = 4;
+                self.len |= (b as usize) << 7;
+                self.start_info();
+            }
+            DecodeState::Info => {
+                self.info[self.pos] = b;
+                self.pos += 1;
+                if self.pos == self.len {
+                    self.state = DecodeState::Fcs;
+                }
+            }
+            DecodeState::Fcs => {
+                self.state = if b == fcs(&self.header[..self.header_len]) {
+                    DecodeState::End
+                } else {
+                    warn!("CMUX frame with bad FCS dropped");
+                    DecodeState::Flag
+                };
+            }
+            DecodeState::End => {
+                if b != FLAG {
+                    self.state = DecodeState::Flag;
+                    return None;
+                }
+                // The closing flag may open the next frame.
+                self.state = DecodeState::Address;
+                let kind = FrameKind::from_control(self.header[1])?;
+                return Some(Frame { dlci: self.header[0] >> 2, kind, info: &self.info[..self.len] });
+            }
+        }
+        None
+    }
+
+    fn start_info(&mut self) {
+        self.pos = 0;
+        self.state = if self.len > CMUX_FRAME_SIZE {
+            warn!("CMUX frame of {} bytes too long", self.len);
+            DecodeState::Flag
+        } else if self.len == 0 {
+            DecodeState::Fcs
+        } else {
+            DecodeState::Info
+        };
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CmuxError {
+    /// The channel is not open.
+    Closed,
+    /// Writing to the UART failed.
+    Link,
+    /// Received data for the channel was lost.
+    Overrun,
+}
+
+impl core::fmt::Display for CmuxError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl core::error::Error for CmuxError {}
+
+impl embedded_io_async::Error for CmuxError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            CmuxError::Closed => ErrorKind::NotConnected,
+            CmuxError::Link | CmuxError::Overrun => ErrorKind::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkState {
+    /// No framing, `CMUX_AT_DLCI` is the raw UART. Also where the link
+    /// returns to if the module does not open the control or the AT channel.
+    Plain,
+    /// Opening the channels after `AT+CMUX`.
+    Opening,
+    Multiplexed,
+}
+
+#[derive(Clone, Copy)]
+struct State {
+    link: LinkState,
+    /// Bit per open DLCI.
+    open: u8,
+    /// Bit per DLCI that lost received data.
+    errors: u8,
+}
+
+/// Channel waiting for its UA.
+#[derive(Clone, Copy)]
+struct Opening {
+    dlci: u8,
+    attempts: u8,
+    deadline: Instant,
+}
+
+/// Multiplexer owning the UART; `run` demultiplexes the received bytes and
+/// `port` hands out the virtual channels.
+pub struct Cmux<M: RawMutex, T> {
+    tx: Mutex<M, T>,
+    /// Received data of DLCI 1..=`CMUX_CHANNELS`.
+    channels: [Pipe<M, CMUX_CHANNEL_BUFFER_SIZE>; CMUX_CHANNELS],
+    state: BlockingMutex<M, Cell<State>>,
+    request: Signal<M, bool>,
+}
+
+impl<M: RawMutex, T: Write> Cmux<M, T> {
+    pub const fn new(tx: T) -> Self {
+        Self {
+            tx: Mutex::new(tx),
+            channels: [const { Pipe::new() }; CMUX_CHANNELS],
+            state: BlockingMutex::new(Cell::new(State { link: LinkState::Plain, open: 0, errors: 0 })),
+            request: Signal::new(),
+        }
+    }
+
+    /// Starts framing once the module accepted `AT+CMUX`, or falls back to
+    /// the plain link when the module was switched off or rebooted.
+    pub fn set_multiplexed(&self, on: bool) {
+        let link = if on { LinkState::Opening } else { LinkState::Plain };
+        self.update(|state| {
+            state.link = link;
+            state.open = 0;
+        });
+        self.request.signal(on);
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        self.state.lock(|state| state.get().link)
+    }
+
+    pub fn is_open(&self, dlci: u8) -> bool {
+        self.state.lock(|state| state.get().open & (1 << dlci) != 0)
+    }
+
+    /// Runs `f` on the UART transmitter (e.g. to change its rate), unless a
+    /// write is in progress.
+    pub fn try_with_tx<U>(&self, f: impl FnOnce(&mut T) -> U) -> Option<U> {
+        self.tx.try_lock().ok().map(|mut tx| f(&mut tx))
+    }
+
+    /// Writer and reader of a virtual channel, DLCI 1..=`CMUX_CHANNELS`.
+    pub fn port(&self, dlci: u8) -> (CmuxWriter<'_, M, T>, CmuxReader<'_, M, T>) {
+        assert!((1..=CMUX_CHANNELS as u8).contains(&dlci));
+        (CmuxWriter { cmux: self, dlci }, CmuxReader { cmux: self, dlci })
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        self.state.lock(|cell| {
+            let mut state = cell.get();
+            f(&mut state);
+            cell.set(state);
+        });
+    }
+
+    fn channel(&self, dlci: u8) -> &Pipe<M, CMUX_CHANNEL_BUFFER_SIZE> {
+        &self.channels[dlci as usize - 1]
+    }
+
+    fn take_error(&self, dlci: u8) -> bool {
+        self.state.lock(|cell| {
+            let mut state = cell.get();
+            let lost = state.errors & (1 << dlci) != 0;
+            state.errors &= !(1 << dlci);
+            cell.set(state);
+            lost
+        })
+    }
+
+    /// Hands received data to a channel. The AT channel waits for its
+    /// reader, leaving the rest in the UART's DMA ring, which reports an
+    /// overrun if it wraps. On the other channels data that does not fit is
+    /// dropped and reported to their reader instead of stalling the link.
+    async fn deliver(&self, dlci: u8, data: &[u8]) {
+        if dlci == CMUX_AT_DLCI {
+            self.channel(dlci).write_all(data).await;
+        } else if self.channel(dlci).try_write(data).ok() != Some(data.len()) {
+            warn!("CMUX channel {} full, data lost", dlci);
+            self.update(|state| state.errors |= 1 << dlci);
+        }
+    }
+
+    async fn send_frame(&self, dlci: u8, kind: FrameKind, info: &[u8]) -> Result<(), CmuxError> {
+        let mut frame = [0u8; MAX_ENCODED_FRAME];
+        let len = encode_frame(dlci, kind, info, &mut frame);
+        let mut tx = self.tx.lock().await;
+        tx.write_all(&frame[..len]).await.map_err(|_| CmuxError::Link)
+    }
+
+    async fn open(&self, dlci: u8, attempts: u8) -> Opening {
+        if let Err(e) = self.send_frame(dlci, FrameKind::Sabm, &[]).await {
+            warn!("CMUX SABM on {} not sent: {:?}", dlci, e);
+        }
+        Opening { dlci, attempts, deadline: Instant::now() + Duration::from_millis(CMUX_OPEN_TIMEOUT_MS) }
+    }
+
+    /// Opens the channel after `dlci`, or completes the start-up.
+    async fn open_next(&self, dlci: u8) -> Option<Opening> {
+        if dlci < CMUX_CHANNELS as u8 {
+            return Some(self.open(dlci + 1, 1).await);
+        }
+        info!("CMUX started");
+        self.update(|state| state.link = LinkState::Multiplexed);
+        None
+    }
+
+    /// Receives from the UART and runs the channel start-up; never returns.
+    pub async fn run<R: Read>(&self, mut rx: R) {
+        let mut buf = [0u8; SIM800_RX_CHUNK_SIZE];
+        let mut decoder = FrameDecoder::new();
+        let mut opening: Option<Opening> = None;
+
+        loop {
+            let timeout = async {
+                match opening {
+                    Some(opening) => Timer::at(opening.deadline).await,
+                    None => core::future::pending().await,
+                }
+            };
+            match select3(rx.read(&mut buf), self.request.wait(), timeout).await {
+                Either3::First(Ok(n)) => {
+                    if self.link_state() == LinkState::Plain {
+                        self.deliver(CMUX_AT_DLCI, &buf[..n]).await;
+                        continue;
+                    }
+                    for &b in &buf[..n] {
+                        if let Some(frame) = decoder.push(b) {
+                            self.handle_frame(frame, &mut opening).await;
+                        }
+                    }
+                }
+                Either3::First(Err(_)) => {
+                    warn!("CMUX receiver error");
+                    let lost = match self.link_state() {
+                        LinkState::Plain => 1 << CMUX_AT_DLCI,
+                        _ => self.state.lock(|state| state.get().open),
+                    };
+                    self.update(|state| state.errors |= lost);
+                }
+                Either3::Second(on) => {
+                    decoder.reset();
+                    opening = None;
+                    if on {
+                        info!("CMUX opening channels");
+                        opening = Some(self.open(CMUX_CONTROL_DLCI, 1).await);
+                    }
+                }
+                Either3::Third(()) => {
+                    let Some(timed_out) = opening else { continue };
+                    if timed_out.attempts < CMUX_OPEN_RETRIES {
+                        opening = Some(self.open(timed_out.dlci, timed_out.attempts + 1).await);
+                    } else {
+                        opening = self.refused(timed_out.dlci).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The module did not open `dlci`. Only the auxiliary channel is optional;
+    /// without the control or the AT channel the multiplexer is closed down,
+    /// in case the module runs it after all, and the link goes back to plain.
+    async fn refused(&self, dlci: u8) -> Option<Opening> {
+        if dlci <= CMUX_AT_DLCI {
+            error!("CMUX channel {} not opened, back to the plain link", dlci);
+            self.send_frame(CMUX_CONTROL_DLCI, FrameKind::Uih, &[CLD | CR, EA]).await.ok();
+            self.update(|state| {
+                state.link = LinkState::Plain;
+                state.open = 0;
+            });
+            return None;
+        }
+        warn!("CMUX channel {} not opened", dlci);
+        self.open_next(dlci).await
+    }
+
+    async fn handle_frame(&self, frame: Frame<'_>, opening: &mut Option<Opening>) {
+        let dlci = frame.dlci;
+        match frame.kind {
+            FrameKind::Ua if opening.is_some_and(|o| o.dlci == dlci) => {
+                self.update(|state| state.open |= 1 << dlci);
+                if dlci != CMUX_CONTROL_DLCI {
+                    let msc = [MSC | CR, (2 << 1) | EA, (dlci << 2) | CR | EA, MSC_SIGNALS];
+                    self.send_frame(CMUX_CONTROL_DLCI, FrameKind::Uih, &msc).await.ok();
+                }
+                *opening = self.open_next(dlci).await;
+            }
+            FrameKind::Dm if opening.is_some_and(|o| o.dlci == dlci) => {
+                *opening = self.refused(dlci).await;
+            }
+            FrameKind::Disc => {
+                info!("CMUX channel {} closed by the module", dlci);
+                self.send_frame(dlci, FrameKind::Ua, &[]).await.ok();
+                if dlci == CMUX_CONTROL_DLCI {
+                    self.update(|state| {
+                        state.link = LinkState::Plain;
+                        state.open = 0;
+                    });
+                } else {
+                    self.update(|state| state.open &= !(1 << dlci));
+                }
+            }
+            // Control channel commands (MSC, test) are acknowledged by
+            // echoing them as responses.
+            FrameKind::Uih if dlci == CMUX_CONTROL_DLCI => {
+                if let Some((&kind, rest)) = frame.info.split_first()
+                    && kind & CR != 0 {
+                    let mut reply = [0u8; CMUX_FRAME_SIZE];
+                    reply[0] = kind & !CR;
+                    reply[1..frame.info.len()].copy_from_slice(rest);
+                    self.send_frame(CMUX_CONTROL_DLCI, FrameKind::Uih, &reply[..frame.info.len()]).await.ok();
+                }
+            }
+            FrameKind::Uih if self.is_open(dlci) && dlci as usize <= CMUX_CHANNELS => {
+                self.deliver(dlci, frame.info).await;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sending half of a virtual channel.
+pub struct CmuxWriter<'a, M: RawMutex, T> {
+    cmux: &'a Cmux<M, T>,
+    dlci: u8,
+}
+
+/// Receiving half of a virtual channel.
+pub struct CmuxReader<'a, M: RawMutex, T> {
+    cmux: &'a Cmux<M, T>,
+    dlci: u8,
+}
+
+impl<M: RawMutex, T> ErrorType for CmuxWriter<'_, M, T> {
+    type Error = CmuxError;
+}
+
+impl<M: RawMutex, T> ErrorType for CmuxReader<'_, M, T> {
+    type Error = CmuxError;
+}
+
+impl<M: RawMutex, T: Write> Write for CmuxWriter<'_, M, T> {
+    /// Waits while the channels are being opened; fails on a channel that
+    /// is not open.
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, CmuxError> {
+        loop {
+            let link = self.cmux.link_state();
+            if link != LinkState::Plain && self.cmux.is_open(self.dlci) {
+                let len = buf.len().min(CMUX_FRAME_SIZE);
+                self.cmux.send_frame(self.dlci, FrameKind::Uih, &buf[..len]).await?;
+                return Ok(len);
+            }
+            match link {
+                LinkState::Plain if self.dlci == CMUX_AT_DLCI => {
+                    let mut tx = self.cmux.tx.lock().await;
+                    return tx.write(buf).await.map_err(|_| CmuxError::Link);
+                }
+                LinkState::Opening => Timer::after(Duration::from_millis(10)).await,
+                _ => return Err(CmuxError::Closed),
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), CmuxError> {
+        self.cmux.tx.lock().await.flush().await.map_err(|_| CmuxError::Link)
+    }
+}
+
+impl<M: RawMutex, T: Write> Read for CmuxReader<'_, M, T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, CmuxError> {
+        if self.cmux.take_error(self.dlci) {
+            return Err(CmuxError::Overrun);
+        }
+        Ok(self.cmux.channel(self.dlci).read(buf).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(decoder: &mut FrameDecoder, bytes: &[u8]) -> Option<(u8, FrameKind, heapless::Vec<u8, CMUX_FRAME_SIZE>)> {
+        let mut found = None;
+        for &b in bytes {
+            if let Some(frame) = decoder.push(b) {
+                found = Some((frame.dlci, frame.kind, heapless::Vec::from_slice(frame.info).unwrap()));
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn sabm_on_control_channel_matches_the_spec() {
+        let mut out = [0u8; MAX_ENCODED_FRAME];
+        let len = encode_frame(CMUX_CONTROL_DLCI, FrameKind::Sabm, &[], &mut out);
+        assert_eq!(&out[..len], &[0xF9, 0x03, 0x3F, 0x01, 0x1C, 0xF9]);
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut out = [0u8; MAX_ENCODED_FRAME];
+        let len = encode_frame(CMUX_AT_DLCI, FrameKind::Uih, b"AT\r\n", &mut out);
+        let mut decoder = FrameDecoder::new();
+        let (dlci, kind, info) = decode_all(&mut decoder, &out[..len]).unwrap();
+        assert_eq!((dlci, kind, info.as_slice()), (CMUX_AT_DLCI, FrameKind::Uih, &b"AT\r\n"[..]));
+    }
+
+    #[test]
+    fn bad_fcs_is_dropped_and_decoder_resyncs() {
+        let mut out = [0u8; MAX_ENCODED_FRAME];
+        let len = encode_frame(CMUX_AUX_DLCI, FrameKind::Uih, b"OK", &mut out);
+        let mut corrupted = out;
+        corrupted[len - 2] ^= 0x55;
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decode_all(&mut decoder, &corrupted[..len]), None);
+
+        let (dlci, _, info) = decode_all(&mut decoder, &out[..len]).unwrap();
+        assert_eq!((dlci, info.as_slice()), (CMUX_AUX_DLCI, &b"OK"[..]));
+    }
+}
@@ -0,0 +1,100 @@
+// /embassy800c-core/src/constants.rs
+pub const ALARMS_CHANNELS_AMOUNT: usize = 3;
+pub const ALARMS_STACK_DEPTH: usize = 3;
+pub const ALARMS_BUFFER_SIZE: usize = 256;
+pub const ALARMS_MESSAGE_STRING_LENGTH: usize = 3;
+
+pub const SMS_PREFIX: &str = "PPP";
+pub const SMS_DIVIDER: &str = "_";
+pub const ONLINE_SIGNAL: &str = "*";
+pub const CONFIRMATION_SIGNAL: &str = "#";
+pub const ERROR_SIGNAL: &str = "0";
+pub const DTMF_PACKET_LENGTH: usize = 3;
+
+pub const MAX_PHONE_LENGTH: usize = 16;
+pub const USSD_CODE_LENGTH: usize = 16;
+pub const ICCID_LENGTH: usize = 20;
+
+pub const SIM800_LINE_BUFFER_SIZE: usize = 64;
+pub const MAXIMUM_DTMF_BUFFER_SIZE: usize = 16;
+pub const MAXIMUM_SIM800_LINE_COUNT: usize = 8;
+pub const MAXIMUM_INCOMING_SMS_BUFFER_SIZE: usize = 8;
+pub const MAXIMUM_PENDING_EVENTS: usize = 4;
+pub const MAXIMUM_DEFERRED_COMMANDS: usize = 2;
+
+pub const SIM800_RX_BUFFER_SIZE: usize = 256;
+pub const SIM800_RX_CHUNK_SIZE: usize = 32;
+/// Fits the hex PDU line of a full SMS (service centre address and 164 octets).
+pub const SIM800_MAX_LINE_LENGTH: usize = 360;
+/// Service centre address and the longest TPDU.
+pub const SMS_PDU_MAX_OCTETS: usize = 176;
+
+pub const SIM800_PWRKEY_PULSE_MS: u64 = 1200;
+pub const SIM800_BOOT_TIMEOUT_SECONDS: u32 = 5;
+pub const SIM800_READY_TIMEOUT_SECONDS: u32 = 30;
+pub const SIM800_READY_POLL_SECONDS: u32 = 5;
+pub const SIM800_POWER_DOWN_TIMEOUT_SECONDS: u32 = 3;
+pub const SIM800_PING_PERIOD_SECONDS: u32 = 60;
+pub const SIM800_MAX_PING_FAILURES: u8 = 3;
+pub const SIM800_POWER_OFF_SECONDS: u32 = 2;
+pub const SIM800_REGISTRATION_TIMEOUT_SECONDS: u32 = 300;
+
+pub const SUPPLY_LOW_MILLIVOLTS: u16 = 3600;
+pub const SUPPLY_RESTORED_MILLIVOLTS: u16 = 3800;
+pub const SIM800_USSD_TIMEOUT_SECONDS: u32 = 15;
+
+pub const JAMMING_WINDOW_SECONDS: u32 = 120;
+pub const JAMMING_SIGNAL_DROP_DB: i16 = 20;
+pub const JAMMING_MIN_INDICATORS: usize = 2;
+
+/// Virtual channels besides the control channel (AT, auxiliary AT).
+pub const CMUX_CHANNELS: usize = 2;
+/// N1, the longest information field of a frame.
+pub const CMUX_FRAME_SIZE: usize = 127;
+/// Received data of a channel not read yet. The AT channel does not drop
+/// what does not fit but leaves it in the UART's DMA ring.
+pub const CMUX_CHANNEL_BUFFER_SIZE: usize = 128;
+pub const CMUX_OPEN_TIMEOUT_MS: u64 = 500;
+pub const CMUX_OPEN_RETRIES: u8 = 3;
+
+/// Time the module needs after DTR went low before it accepts commands.
+pub const SIM800_WAKE_DELAY_MS: u64 = 50;
+
+/// Rates tried, in order, when the module does not answer at the UART rate.
+pub const SIM800_BAUD_RATES: [u32; 6] = [115200, 57600, 38400, 19200, 9600, 4800];
+pub const SIM800_BAUD_PROBE_MS: u64 = 300;
+
+/// Text of an SMS as decoded or reassembled, concatenated ones included.
+pub const SMS_TEXT_LENGTH: usize = 160;
+/// Text of an outbox report. Every entry is saved to flash with room for
+/// this much, so it is kept apart from `SMS_TEXT_LENGTH`; the longest report
+/// (a forwarded USSD answer) fits.
+pub const SMS_MESSAGE_LENGTH: usize = 64;
+/// Parts of a concatenated SMS kept for reassembly.
+pub const SMS_MAX_PARTS: usize = 3;
+/// Concatenated SMS reassembled at the same time.
+pub const SMS_CONCAT_SLOTS: usize = 2;
+pub const SMS_CONCAT_TIMEOUT_SECONDS: u32 = 120;
+
+/// Sent SMS awaiting their status report.
+pub const MAXIMUM_TRACKED_SMS: usize = 2;
+/// After this long without a final status report an SMS is reported unconfirmed.
+pub const SMS_DELIVERY_TIMEOUT_SECONDS: u32 = 600;
+
+/// Alarm reports waiting to go out.
+pub const OUTBOX_SIZE: usize = 4;
+/// Wait after the first failed attempt, doubled with every further one.
+pub const OUTBOX_RETRY_BASE_SECONDS: u32 = 10;
+pub const OUTBOX_RETRY_MAX_SECONDS: u32 = 1800;
+pub const OUTBOX_MAX_ATTEMPTS: u8 = 10;
+/// Reports still not sent this long after they were queued are given up.
+pub const OUTBOX_EXPIRY_SECONDS: u32 = 24 * 3600;
+/// A report the driver did not answer for this long counts as failed.
+pub const OUTBOX_SEND_TIMEOUT_SECONDS: u32 = 900;
+/// Changes to the outbox are saved together this long after the first one;
+/// a reset in between loses them.
+pub const OUTBOX_SAVE_DELAY_SECONDS: u32 = 5;
+
+/// Stored SMS taken off the SIM per `AT+CMGL` sweep.
+pub const SMS_SWEEP_BATCH: usize = 8;
+pub const SIM800_SMS_LIST_TIMEOUT_SECONDS: u32 = 20;
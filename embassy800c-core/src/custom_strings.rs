@@ -0,0 +1,160 @@
+// /embassy800c-core/src/custom_strings.rs
+use heapless::String;
+
+/// Returns the substring after `delimiter`, if present.
+pub fn extract_after_delimiter<'a>(input: &'a str, delimiter: &str) -> Option<&'a str> {
+    input.split_once(delimiter).map(|(_, suffix)| suffix)
+}
+
+/// Returns the substring before `delimiter`, if present.
+pub fn extract_before_delimiter<'a>(input: &'a str, delimiter: &str) -> Option<&'a str> {
+    input.split_once(delimiter).map(|(prefix, _)| prefix)
+}
+
+/// Returns the first substring found between `start_delim` and `end_delim`, if present.
+///
+/// Example:
+/// ```
+/// # use embassy800c_core::custom_strings::extract_between_delimiters;
+/// let s = r#"+CPBR: 2,"*105#",129,"0""#;
+/// assert_eq!(extract_between_delimiters(s, "\"", "\""), Some("*105#"));
+/// ```
+pub fn extract_between_delimiters<'a>(
+    input: &'a str,
+    start_delim: &str,
+    end_delim: &str,
+) -> Option<&'a str> {
+    // Find the start delimiter
+    let start_index = input.find(start_delim)?;
+    let after_start = &input[start_index + start_delim.len()..];
+
+    // Find the end delimiter after the start delimiter
+    let end_index = after_start.find(end_delim)?;
+    Some(&after_start[..end_index])
+}
+
+/// Takes an immutable &str as input (e.g. "456"),
+/// Inserts commas between characters (producing "4,5,6"),
+/// Returns the result as a &str,
+pub fn separate_chars_by_commas<'a>(
+    input: &str,
+    output: &'a mut [u8],
+) -> Option<&'a str> {
+    let bytes = input.as_bytes();
+    let len = bytes.len();
+
+    if len == 0 {
+        return Some("");
+    }
+
+    let required_len = len + (len - 1);
+    if output.len() < required_len {
+        return None;
+    }
+
+    let mut i = 0;
+    for (idx, &ch) in bytes.iter().enumerate() {
+        output[i] = ch;
+        i += 1;
+        if idx != len - 1 {
+            output[i] = b',';
+            i += 1;
+        }
+    }
+
+    // SAFETY: All bytes come from valid UTF-8 input + ASCII commas
+    str::from_utf8(&output[..i]).ok()
+}
+
+/// Decodes UCS2 text given as hex digits, four per character (`"04110430"`),
+/// as the modem reports it for UCS2 coded USSD and SMS. Characters beyond
+/// the capacity are dropped; `None` if `hex` is not UCS2 hex.
+pub fn decode_ucs2_hex<const N: usize>(hex: &str) -> Option<String<N>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = String::new();
+    for unit in hex.as_bytes().chunks(4) {
+        let unit = u32::from_str_radix(str::from_utf8(unit).ok()?, 16).ok()?;
+        // Lone surrogates cannot be represented.
+        let c = char::from_u32(unit).unwrap_or('?');
+        if out.push(c).is_err() {
+            break;
+        }
+    }
+    Some(out)
+}
+
+/// First amount in `text` in hundredths, e.g. `"Balance: -12,5 rub"` is -1250.
+/// Both `.` and `,` are accepted as decimal separator.
+pub fn parse_amount(text: &str) -> Option<i32> {
+    let bytes = text.as_bytes();
+    let start = bytes.iter().position(u8::is_ascii_digit)?;
+    let negative = start > 0 && bytes[start - 1] == b'-';
+
+    let mut whole: i32 = 0;
+    let mut i = start;
+    while i < bytes.len() && bytes[i].is_ascii_digit() {
+        whole = whole.checked_mul(10)?.checked_add((bytes[i] - b'0') as i32)?;
+        i += 1;
+    }
+    let mut cents = 0;
+    if i + 1 < bytes.len() && matches!(bytes[i], b'.' | b',') && bytes[i + 1].is_ascii_digit() {
+        cents = (bytes[i + 1] - b'0') as i32 * 10;
+        if i + 2 < bytes.len() && bytes[i + 2].is_ascii_digit() {
+            cents += (bytes[i + 2] - b'0') as i32;
+        }
+    }
+    let amount = whole.checked_mul(100)?.checked_add(cents)?;
+    Some(if negative { -amount } else { amount })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_around_delimiters() {
+        assert_eq!(extract_after_delimiter("+DTMF: 5", "+DTMF: "), Some("5"));
+        assert_eq!(extract_before_delimiter("123;rest", ";"), Some("123"));
+        assert_eq!(extract_before_delimiter("123", ";"), None);
+    }
+
+    #[test]
+    fn extracts_between_delimiters() {
+        let line = r#"+CPBR: 1,"+79001234567",145,"Admin""#;
+        assert_eq!(extract_between_delimiters(line, ",\"", "\","), Some("+79001234567"));
+        assert_eq!(extract_between_delimiters("no quotes", "\"", "\""), None);
+    }
+
+    #[test]
+    fn separates_chars_by_commas() {
+        let mut out = [0u8; 8];
+        assert_eq!(separate_chars_by_commas("456", &mut out), Some("4,5,6"));
+        assert_eq!(separate_chars_by_commas("", &mut out), Some(""));
+
+        let mut small = [0u8; 4];
+        assert_eq!(separate_chars_by_commas("456", &mut small), None);
+    }
+
+    #[test]
+    fn decodes_ucs2_hex() {
+        let text: String<16> = decode_ucs2_hex("0411043000200031002E0035").unwrap();
+        assert_eq!(text.as_str(), "Ба 1.5");
+        let short: String<2> = decode_ucs2_hex("004100420043").unwrap();
+        assert_eq!(short.as_str(), "AB");
+        assert_eq!(decode_ucs2_hex::<8>("Balance"), None);
+        assert_eq!(decode_ucs2_hex::<8>("00G1"), None);
+        assert_eq!(decode_ucs2_hex::<8>(""), None);
+    }
+
+    #[test]
+    fn parses_first_amount() {
+        assert_eq!(parse_amount("Balance: 123.45 rub"), Some(12345));
+        assert_eq!(parse_amount("Balans -12,5r. Tarif 7"), Some(-1250));
+        assert_eq!(parse_amount("Ostatok 40 min"), Some(4000));
+        assert_eq!(parse_amount("Dolg 3."), Some(300));
+        assert_eq!(parse_amount("no digits"), None);
+        assert_eq!(parse_amount("99999999999"), None);
+    }
+}
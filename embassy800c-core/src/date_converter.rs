@@ -1,7 +1,7 @@
-// /src/date_converter.rs
+// /embassy800c-core/src/date_converter.rs
 use core::fmt::{self, Write};
 
-use crate::rtc::GsmTime;
+use crate::gsm_time_converter::GsmTime;
 
 // Fixed buffer to format "yymmddhhmmss" (12 characters)
 pub struct TimeBuffer {
@@ -22,6 +22,12 @@ impl TimeBuffer {
     }
 }
 
+impl Default for TimeBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Write for TimeBuffer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         let bytes = s.as_bytes();
@@ -45,4 +51,14 @@ pub fn format_gsm_time(time: &GsmTime) -> TimeBuffer {
         time.second
     ).unwrap();
     buf
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_zero_padded_timestamp() {
+        let time = GsmTime { year: 26, month: 1, day: 9, hour: 3, minute: 5, second: 7 };
+        assert_eq!(format_gsm_time(&time).as_str(), "260109030507");
+    }
+}
@@ -1,12 +1,24 @@
+// /embassy800c-core/src/gsm_time_converter.rs
 use heapless::Vec;
 
-use crate::rtc::GsmTime;
+use crate::custom_strings::extract_between_delimiters;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GsmTime{
+    pub year: u8,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
 
 impl GsmTime {
     fn parse_u8(s: &[u8]) -> Option<u8> {
         let mut result = 0u8;
         for &byte in s {
-            if byte < b'0' || byte > b'9' {
+            if !byte.is_ascii_digit() {
                 return None;
             }
             let digit = byte - b'0';
@@ -18,7 +30,7 @@ impl GsmTime {
     fn parse_u16_to_u8_year(s: &[u8]) -> Option<u8> {
         let mut result = 0u16;
         for &byte in s {
-            if byte < b'0' || byte > b'9' {
+            if !byte.is_ascii_digit() {
                 return None;
             }
             let digit = (byte - b'0') as u16;
@@ -38,7 +50,7 @@ impl GsmTime {
                 break;
             }
             
-            if byte >= b'0' && byte <= b'9' {
+            if byte.is_ascii_digit() {
                 // Copy digit
                 result_buf[result_len] = byte;
             } else {
@@ -89,11 +101,73 @@ impl GsmTime {
         let second = Self::parse_u8(valid_parts[5])?;
 
         // Validate ranges
-        if month < 1 || month > 12 || day < 1 || day > 31 || 
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) ||
            hour > 23 || minute > 59 || second > 59 {
             return None;
         }
 
         Some(GsmTime { year, month, day, hour, minute, second })
     }
-}
\ No newline at end of file
+
+    /// Parses the quoted timestamp of a `+CCLK:` reply.
+    ///
+    /// Example: `+CCLK: "26/01/09,23:15:31+12"`
+    pub fn parse_cclk(line: &str) -> Option<GsmTime> {
+        let content = extract_between_delimiters(line, "\"", "\"")?;
+        let bytes = content.as_bytes();
+        if bytes.len() < 17 { return None; }
+
+        let parse2 = |i: usize| -> Option<u8> {
+            let d1 = bytes[i].wrapping_sub(b'0');
+            let d2 = bytes[i+1].wrapping_sub(b'0');
+            if d1 > 9 || d2 > 9 { return None; }
+            Some(d1 * 10 + d2)
+        };
+
+        Some(GsmTime {
+            year: parse2(0)?,
+            month: parse2(3)?,
+            day: parse2(6)?,
+            hour: parse2(9)?,
+            minute: parse2(12)?,
+            second: parse2(15)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZERO: GsmTime = GsmTime { year: 0, month: 0, day: 0, hour: 0, minute: 0, second: 0 };
+
+    #[test]
+    fn parses_cclk_reply() {
+        let time = GsmTime::parse_cclk(r#"+CCLK: "26/01/09,23:15:31+12""#);
+        assert_eq!(
+            time,
+            Some(GsmTime { year: 26, month: 1, day: 9, hour: 23, minute: 15, second: 31 })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_cclk() {
+        assert_eq!(GsmTime::parse_cclk("+CCLK: 26/01/09,23:15:31+12"), None);
+        assert_eq!(GsmTime::parse_cclk(r#"+CCLK: "26/01/09,23:15""#), None);
+        assert_eq!(GsmTime::parse_cclk(r#"+CCLK: "26/0a/09,23:15:31+12""#), None);
+    }
+
+    #[test]
+    fn parses_free_form_dates() {
+        let expected = Some(GsmTime { year: 26, month: 1, day: 9, hour: 23, minute: 15, second: 31 });
+        assert_eq!(ZERO.parse_gsm_time("26/01/09,23:15:31"), expected);
+        assert_eq!(ZERO.parse_gsm_time("2026-01-09 23:15:31"), expected);
+    }
+
+    #[test]
+    fn rejects_out_of_range_dates() {
+        assert_eq!(ZERO.parse_gsm_time("26/13/09,23:15:31"), None);
+        assert_eq!(ZERO.parse_gsm_time("26/01/09,24:15:31"), None);
+        assert_eq!(ZERO.parse_gsm_time("26/01/09"), None);
+    }
+}
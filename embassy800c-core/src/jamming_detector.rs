@@ -0,0 +1,214 @@
+// /embassy800c-core/src/jamming_detector.rs
+//! Possible GSM jamming, concluded from several independent anomalies
+//! (deregistration, a sudden signal drop, failed call setups) seen within a
+//! short window. A single one is normal coverage trouble.
+
+use embassy_time::{Duration, Instant};
+
+use crate::at_parser::RegistrationStatus;
+use crate::constants::{JAMMING_MIN_INDICATORS, JAMMING_SIGNAL_DROP_DB, JAMMING_WINDOW_SECONDS};
+use crate::signal_quality::SignalQuality;
+use crate::sim800::Sim800Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum JammingIndicator {
+    /// `+CREG` left the registered state.
+    Deregistered,
+    /// `AT+CSQ` fell by `JAMMING_SIGNAL_DROP_DB` or became unknown.
+    SignalDrop,
+    /// An alarm call could not be set up.
+    CallSetupFailed,
+}
+
+impl JammingIndicator {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+const INDICATOR_COUNT: usize = 3;
+
+/// Anomalies seen from the start of a jamming episode until registration
+/// returned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct JammingEpisode {
+    pub duration_secs: u32,
+    pub deregistrations: u8,
+    pub signal_drops: u8,
+    pub call_failures: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum JammingChange {
+    /// At least `JAMMING_MIN_INDICATORS` different anomalies within
+    /// `JAMMING_WINDOW_SECONDS`.
+    Suspected,
+    /// Registered again after a suspected episode.
+    Ended(JammingEpisode),
+}
+
+pub struct JammingDetector {
+    /// Last time each indicator was seen.
+    seen: [Option<Instant>; INDICATOR_COUNT],
+    /// Indicator counts of the running episode.
+    counts: [u8; INDICATOR_COUNT],
+    jammed_since: Option<Instant>,
+    registered: bool,
+    last_dbm: Option<i16>,
+}
+
+impl JammingDetector {
+    pub const fn new() -> Self {
+        Self {
+            seen: [None; INDICATOR_COUNT],
+            counts: [0; INDICATOR_COUNT],
+            jammed_since: None,
+            registered: false,
+            last_dbm: None,
+        }
+    }
+
+    pub fn is_jammed(&self) -> bool {
+        self.jammed_since.is_some()
+    }
+
+    /// Feeds a `+CREG` state.
+    pub fn registration(&mut self, status: RegistrationStatus, now: Instant) -> Option<JammingChange> {
+        let was_registered = self.registered;
+        self.registered = status.is_registered();
+        if self.registered {
+            return self.jammed_since.take().map(|since| self.end_episode(since, now));
+        }
+        // A denied registration is the network refusing the SIM, not a jammer.
+        if was_registered && status != RegistrationStatus::Denied {
+            return self.indicate(JammingIndicator::Deregistered, now);
+        }
+        None
+    }
+
+    /// Feeds an `AT+CSQ` reading.
+    pub fn signal(&mut self, quality: SignalQuality, now: Instant) -> Option<JammingChange> {
+        let dbm = quality.dbm();
+        let dropped = match (self.last_dbm, dbm) {
+            (Some(last), Some(current)) => last - current >= JAMMING_SIGNAL_DROP_DB,
+            (Some(_), None) => true,
+            _ => false,
+        };
+        self.last_dbm = dbm;
+        if dropped {
+            self.indicate(JammingIndicator::SignalDrop, now)
+        } else {
+            None
+        }
+    }
+
+    /// Feeds the result of an alarm call.
+    pub fn call_result(&mut self, result: Result<(), Sim800Error>, now: Instant) -> Option<JammingChange> {
+        match result {
+            Err(Sim800Error::NoCarrier | Sim800Error::NoDialtone | Sim800Error::Timeout) => {
+                self.indicate(JammingIndicator::CallSetupFailed, now)
+            }
+            _ => None,
+        }
+    }
+
+    fn indicate(&mut self, indicator: JammingIndicator, now: Instant) -> Option<JammingChange> {
+        self.seen[indicator.index()] = Some(now);
+        if self.is_jammed() {
+            self.counts[indicator.index()] = self.counts[indicator.index()].saturating_add(1);
+            return None;
+        }
+
+        let window = Duration::from_secs(JAMMING_WINDOW_SECONDS as u64);
+        let recent = self
+            .seen
+            .iter()
+            .filter(|seen| seen.is_some_and(|at| now - at <= window))
+            .count();
+        if recent < JAMMING_MIN_INDICATORS {
+            return None;
+        }
+
+        for (count, seen) in self.counts.iter_mut().zip(self.seen.iter()) {
+            *count = u8::from(seen.is_some_and(|at| now - at <= window));
+        }
+        self.jammed_since = Some(now);
+        Some(JammingChange::Suspected)
+    }
+
+    fn end_episode(&mut self, since: Instant, now: Instant) -> JammingChange {
+        let episode = JammingEpisode {
+            duration_secs: (now - since).as_secs() as u32,
+            deregistrations: self.counts[JammingIndicator::Deregistered.index()],
+            signal_drops: self.counts[JammingIndicator::SignalDrop.index()],
+            call_failures: self.counts[JammingIndicator::CallSetupFailed.index()],
+        };
+        self.seen = [None; INDICATOR_COUNT];
+        self.counts = [0; INDICATOR_COUNT];
+        JammingChange::Ended(episode)
+    }
+}
+
+impl Default for JammingDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> Instant {
+        Instant::from_secs(secs)
+    }
+
+    fn csq(rssi: u8) -> SignalQuality {
+        SignalQuality { rssi, ber: 0 }
+    }
+
+    #[test]
+    fn single_anomaly_is_not_jamming() {
+        let mut detector = JammingDetector::new();
+        assert_eq!(detector.registration(RegistrationStatus::Home, at(0)), None);
+        assert_eq!(detector.registration(RegistrationStatus::Searching, at(10)), None);
+        assert_eq!(detector.registration(RegistrationStatus::Home, at(20)), None);
+        assert_eq!(detector.call_result(Err(Sim800Error::Busy), at(30)), None);
+        assert!(!detector.is_jammed());
+    }
+
+    #[test]
+    fn combined_anomalies_raise_and_end_an_episode() {
+        let mut detector = JammingDetector::new();
+        detector.registration(RegistrationStatus::Home, at(0));
+        assert_eq!(detector.signal(csq(20), at(0)), None);
+        assert_eq!(detector.signal(csq(8), at(60)), None);
+        assert_eq!(detector.registration(RegistrationStatus::Searching, at(70)), Some(JammingChange::Suspected));
+        assert!(detector.is_jammed());
+        assert_eq!(detector.call_result(Err(Sim800Error::NoCarrier), at(80)), None);
+
+        assert_eq!(
+            detector.registration(RegistrationStatus::Home, at(370)),
+            Some(JammingChange::Ended(JammingEpisode {
+                duration_secs: 300,
+                deregistrations: 1,
+                signal_drops: 1,
+                call_failures: 1,
+            }))
+        );
+        assert!(!detector.is_jammed());
+    }
+
+    #[test]
+    fn anomalies_outside_the_window_do_not_combine() {
+        let mut detector = JammingDetector::new();
+        detector.signal(csq(20), at(0));
+        assert_eq!(detector.signal(csq(99), at(10)), None);
+        let late = 10 + JAMMING_WINDOW_SECONDS as u64 + 1;
+        assert_eq!(detector.call_result(Err(Sim800Error::NoDialtone), at(late)), None);
+        assert!(!detector.is_jammed());
+    }
+}
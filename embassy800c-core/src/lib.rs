@@ -0,0 +1,22 @@
+// /embassy800c-core/src/lib.rs
+#![no_std]
+
+#[macro_use]
+mod fmt;
+
+pub mod constants;
+pub mod alarms_handler;
+pub mod at_parser;
+pub mod cmux;
+pub mod custom_strings;
+pub mod date_converter;
+pub mod gsm_time_converter;
+pub mod jamming_detector;
+pub mod modem_wake;
+pub mod outbox;
+pub mod phone_book;
+pub mod signal_quality;
+pub mod supply_monitor;
+pub mod sim800;
+pub mod sms_assembler;
+pub mod sms_pdu;
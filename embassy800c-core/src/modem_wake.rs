@@ -0,0 +1,129 @@
+// /embassy800c-core/src/modem_wake.rs
+//! Sleep and wake-up of the SIM800 shared between its AT channels.
+//!
+//! Only the primary `Sim800Driver` drives DTR. Everyone else (the ring
+//! indicator, a `QueryChannel` on the auxiliary CMUX channel) asks it to wake
+//! the module through `ModemWake` instead of queueing behind its commands,
+//! and a `WakeHold` keeps the module awake while another channel uses it.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use crate::constants::SIM800_WAKE_DELAY_MS;
+
+#[derive(Clone, Copy)]
+struct State {
+    /// DTR is high, the module may be sleeping.
+    asleep: bool,
+    /// `WakeHold`s alive.
+    holds: u8,
+}
+
+pub struct ModemWake<M: RawMutex> {
+    request: Signal<M, ()>,
+    state: BlockingMutex<M, Cell<State>>,
+}
+
+impl<M: RawMutex> ModemWake<M> {
+    pub const fn new() -> Self {
+        Self {
+            request: Signal::new(),
+            state: BlockingMutex::new(Cell::new(State { asleep: false, holds: 0 })),
+        }
+    }
+
+    /// Asks the driver to wake the module, e.g. because its ring indicator
+    /// announced a call or SMS. Requests made while one is pending merge.
+    pub fn request(&self) {
+        self.request.signal(());
+    }
+
+    /// Resolves on the next `request`; for the driver's `PowerControl`.
+    pub async fn requested(&self) {
+        self.request.wait().await
+    }
+
+    /// Follows DTR, for the driver's `PowerControl::set_dtr`.
+    pub fn set_asleep(&self, asleep: bool) {
+        self.update(|state| state.asleep = asleep);
+    }
+
+    /// Whether a `WakeHold` forbids the driver to put the module to sleep.
+    pub fn is_held(&self) -> bool {
+        self.state.lock(|state| state.get().holds > 0)
+    }
+
+    /// Keeps the module awake until the hold is dropped, waking it first if
+    /// it sleeps.
+    pub async fn hold(&self) -> WakeHold<'_, M> {
+        let mut asleep = false;
+        self.update(|state| {
+            state.holds += 1;
+            asleep = state.asleep;
+        });
+        let hold = WakeHold { wake: self };
+        if asleep {
+            self.request();
+            while self.state.lock(|state| state.get().asleep) {
+                Timer::after(Duration::from_millis(10)).await;
+            }
+            Timer::after(Duration::from_millis(SIM800_WAKE_DELAY_MS)).await;
+        }
+        hold
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        self.state.lock(|cell| {
+            let mut state = cell.get();
+            f(&mut state);
+            cell.set(state);
+        });
+    }
+}
+
+impl<M: RawMutex> Default for ModemWake<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps the module awake while alive, see `ModemWake::hold`.
+pub struct WakeHold<'a, M: RawMutex> {
+    wake: &'a ModemWake<M>,
+}
+
+impl<M: RawMutex> Drop for WakeHold<'_, M> {
+    fn drop(&mut self) {
+        self.wake.update(|state| state.holds -= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embassy_futures::select::{select, Either};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    #[test]
+    fn hold_wakes_a_sleeping_module_and_keeps_it_awake() {
+        let wake = ModemWake::<NoopRawMutex>::new();
+        wake.set_asleep(true);
+        let driver = async {
+            wake.requested().await;
+            wake.set_asleep(false);
+            core::future::pending::<()>().await
+        };
+        let hold = match block_on(select(wake.hold(), driver)) {
+            Either::First(hold) => hold,
+            Either::Second(()) => unreachable!(),
+        };
+        assert!(wake.is_held());
+        drop(hold);
+        assert!(!wake.is_held());
+    }
+}
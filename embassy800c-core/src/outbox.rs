@@ -0,0 +1,421 @@
+// /embassy800c-core/src/outbox.rs
+//! Alarm reports waiting to go out. They are sent one at a time in the
+//! order they were queued; a failed report is retried with exponential
+//! backoff until it goes through, runs out of attempts or expires. While
+//! reports are backed up the outbox is worth saving, so that a reset during
+//! a network outage does not lose them.
+
+use embassy_time::{Duration, Instant};
+use heapless::{String, Vec};
+
+use crate::constants::{
+    DTMF_PACKET_LENGTH, OUTBOX_EXPIRY_SECONDS, OUTBOX_MAX_ATTEMPTS, OUTBOX_RETRY_BASE_SECONDS,
+    OUTBOX_RETRY_MAX_SECONDS, OUTBOX_SEND_TIMEOUT_SECONDS, OUTBOX_SIZE, SMS_MESSAGE_LENGTH,
+};
+use crate::sim800::Command;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Report {
+    /// Sent as `Command::SendAlarmSms`. Kept to `SMS_MESSAGE_LENGTH`, the
+    /// text of a saved entry, though the command carries a whole SMS.
+    Sms(String<SMS_MESSAGE_LENGTH>),
+    /// Sent as `Command::CallAlarmWithDtmf`.
+    Call(String<DTMF_PACKET_LENGTH>),
+}
+
+const KIND_SMS: u8 = 1;
+const KIND_CALL: u8 = 2;
+
+impl Report {
+    /// Command sending the report for the first time.
+    pub fn command(&self) -> Command {
+        match self {
+            Report::Sms(message) => {
+                let mut text = String::new();
+                let _ = text.push_str(message);
+                Command::SendAlarmSms { message: text, resends: 0 }
+            }
+            Report::Call(dtmf) => Command::CallAlarmWithDtmf { dtmf: dtmf.clone() },
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            Report::Sms(_) => KIND_SMS,
+            Report::Call(_) => KIND_CALL,
+        }
+    }
+
+    fn text(&self) -> &str {
+        match self {
+            Report::Sms(message) => message,
+            Report::Call(dtmf) => dtmf,
+        }
+    }
+
+    fn from_record(kind: u8, text: &str) -> Option<Self> {
+        match kind {
+            KIND_SMS => text.try_into().ok().map(Report::Sms),
+            KIND_CALL => text.try_into().ok().map(Report::Call),
+            _ => None,
+        }
+    }
+}
+
+struct Entry {
+    report: Report,
+    /// Attempts that failed so far.
+    failures: u8,
+    /// Times the report went out again after it was not delivered.
+    resends: u8,
+    queued: Instant,
+    /// Earliest time of the next attempt.
+    due: Instant,
+}
+
+impl Entry {
+    fn command(&self) -> Command {
+        let mut command = self.report.command();
+        if let Command::SendAlarmSms { resends, .. } = &mut command {
+            *resends = self.resends;
+        }
+        command
+    }
+}
+
+/// Saved entry layout: kind, failures, text length, resends, text.
+const ENTRY_SIZE: usize = 4 + SMS_MESSAGE_LENGTH;
+const HEADER_SIZE: usize = 4;
+
+pub struct Outbox {
+    /// Oldest first; only the front one is ever being sent.
+    entries: Vec<Entry, OUTBOX_SIZE>,
+    /// When the front report was handed to the driver.
+    in_flight: Option<Instant>,
+    /// Changed since it was last saved, in a way worth saving.
+    dirty: bool,
+    /// The saved copy holds reports.
+    saved: bool,
+}
+
+impl Outbox {
+    /// Size of a saved outbox: report count, padding, the entries.
+    pub const RECORD_SIZE: usize = HEADER_SIZE + OUTBOX_SIZE * ENTRY_SIZE;
+
+    pub const fn new() -> Self {
+        Self { entries: Vec::new(), in_flight: None, dirty: false, saved: false }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Queues a report behind the others. A full outbox gives up its oldest
+    /// report that is not being sent.
+    pub fn push(&mut self, report: Report, now: Instant) {
+        self.queue(report, 0, now);
+    }
+
+    /// Queues a report again that went out but was not delivered, for the
+    /// `resends`th time.
+    pub fn resend(&mut self, report: Report, resends: u8, now: Instant) {
+        self.queue(report, resends, now);
+    }
+
+    fn queue(&mut self, report: Report, resends: u8, now: Instant) {
+        if self.entries.is_full() {
+            let index = usize::from(self.in_flight.is_some() && self.entries.len() > 1);
+            if index == 0 {
+                self.in_flight = None;
+            }
+            let dropped = self.entries.remove(index);
+            error!("Outbox full, dropping {:?}", dropped.report);
+        }
+        let _ = self.entries.push(Entry { report, failures: 0, resends, queued: now, due: now });
+        self.changed();
+    }
+
+    /// Command for the front report once it is due. It counts as being sent
+    /// until `sent` or `failed` is called, or `OUTBOX_SEND_TIMEOUT_SECONDS`
+    /// passed without either.
+    pub fn dispatch(&mut self, now: Instant) -> Option<Command> {
+        if let Some(since) = self.in_flight {
+            if now < since + Self::send_timeout() {
+                return None;
+            }
+            warn!("No outcome of the report being sent");
+            if let Some(report) = self.failed(now, true) {
+                error!("Giving up on {:?}", report);
+            }
+        }
+        self.expire(now);
+        let entry = self.entries.first()?;
+        if now < entry.due {
+            return None;
+        }
+        self.in_flight = Some(now);
+        Some(entry.command())
+    }
+
+    /// When `dispatch` has something to do next.
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.in_flight {
+            Some(since) => Some(since + Self::send_timeout()),
+            None => self.entries.first().map(|entry| entry.due),
+        }
+    }
+
+    /// Whether a report was handed out by `dispatch` and has no outcome yet.
+    pub fn is_sending(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// The report being sent went out.
+    pub fn sent(&mut self) -> Option<Report> {
+        self.in_flight.take()?;
+        let entry = self.entries.remove(0);
+        self.changed();
+        Some(entry.report)
+    }
+
+    /// The report being sent failed. It is tried again after a backoff,
+    /// unless `retry` is false or it ran out of attempts, in which case it
+    /// is returned.
+    pub fn failed(&mut self, now: Instant, retry: bool) -> Option<Report> {
+        self.in_flight.take()?;
+        let entry = &mut self.entries[0];
+        entry.failures = entry.failures.saturating_add(1);
+        let given_up = if !retry || entry.failures >= OUTBOX_MAX_ATTEMPTS {
+            Some(self.entries.remove(0).report)
+        } else {
+            entry.due = now + backoff(entry.failures);
+            None
+        };
+        self.changed();
+        given_up
+    }
+
+    /// Whether the outbox should be saved: reports are backed up, or the
+    /// saved copy is out of date.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Like `is_dirty`, clearing the flag.
+    pub fn take_dirty(&mut self) -> bool {
+        core::mem::take(&mut self.dirty)
+    }
+
+    /// Writes the saved form through `write(offset, bytes)` one entry at a
+    /// time, so that no buffer of the whole record is needed, and the
+    /// header with the report count last. Unused entries are not written.
+    pub fn save<E>(&mut self, mut write: impl FnMut(usize, &[u8]) -> Result<(), E>) -> Result<(), E> {
+        let mut out = [0xFFu8; ENTRY_SIZE];
+        for (i, entry) in self.entries.iter().enumerate() {
+            let text = entry.report.text().as_bytes();
+            out.fill(0xFF);
+            out[0] = entry.report.kind();
+            out[1] = entry.failures;
+            out[2] = text.len() as u8;
+            out[3] = entry.resends;
+            out[4..4 + text.len()].copy_from_slice(text);
+            write(HEADER_SIZE + i * ENTRY_SIZE, &out)?;
+        }
+        write(0, &[self.entries.len() as u8, 0xFF, 0xFF, 0xFF])?;
+        self.saved = !self.entries.is_empty();
+        Ok(())
+    }
+
+    /// Restores saved reports, read through `read(offset, buf)`, behind any
+    /// queued since boot. They are due right away; how long they had been
+    /// queued before the reset is not known, so they expire
+    /// `OUTBOX_EXPIRY_SECONDS` from now.
+    pub fn load<E>(&mut self, mut read: impl FnMut(usize, &mut [u8]) -> Result<(), E>, now: Instant) -> Result<(), E> {
+        let mut header = [0u8; HEADER_SIZE];
+        read(0, &mut header)?;
+        let count = usize::from(header[0]).min(OUTBOX_SIZE);
+        let mut entry = [0u8; ENTRY_SIZE];
+        for i in 0..count {
+            read(HEADER_SIZE + i * ENTRY_SIZE, &mut entry)?;
+            let text = entry.get(4..4 + usize::from(entry[2])).and_then(|text| core::str::from_utf8(text).ok());
+            let Some(report) = text.and_then(|text| Report::from_record(entry[0], text)) else {
+                warn!("Dropping a damaged saved report");
+                continue;
+            };
+            let entry = Entry { report, failures: entry[1], resends: entry[3], queued: now, due: now };
+            if self.entries.push(entry).is_err() {
+                warn!("No room for saved reports");
+                break;
+            }
+        }
+        self.saved = !self.entries.is_empty();
+        Ok(())
+    }
+
+    /// Gives up reports queued for longer than `OUTBOX_EXPIRY_SECONDS`.
+    fn expire(&mut self, now: Instant) {
+        let expiry = Duration::from_secs(OUTBOX_EXPIRY_SECONDS as u64);
+        while let Some(index) = self.entries.iter().position(|entry| now >= entry.queued + expiry) {
+            let entry = self.entries.remove(index);
+            error!("Report expired after {} attempts: {:?}", entry.failures, entry.report);
+            self.changed();
+        }
+    }
+
+    fn changed(&mut self) {
+        self.dirty |= self.saved || self.entries.iter().any(|entry| entry.failures > 0);
+    }
+
+    fn send_timeout() -> Duration {
+        Duration::from_secs(OUTBOX_SEND_TIMEOUT_SECONDS as u64)
+    }
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wait before the next attempt: doubling from `OUTBOX_RETRY_BASE_SECONDS`
+/// with every failure, up to `OUTBOX_RETRY_MAX_SECONDS`.
+fn backoff(failures: u8) -> Duration {
+    let secs = (OUTBOX_RETRY_BASE_SECONDS as u64) << (failures.saturating_sub(1)).min(16);
+    Duration::from_secs(secs.min(OUTBOX_RETRY_MAX_SECONDS as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sms(text: &str) -> Report {
+        Report::Sms(text.try_into().unwrap())
+    }
+
+    fn secs(s: u64) -> Instant {
+        Instant::from_secs(s)
+    }
+
+    #[test]
+    fn reports_go_out_in_order_with_backoff() {
+        let mut outbox = Outbox::new();
+        outbox.push(sms("PPP_101_260109231531"), secs(0));
+        outbox.push(Report::Call("010".try_into().unwrap()), secs(0));
+
+        assert_eq!(outbox.dispatch(secs(0)), Some(sms("PPP_101_260109231531").command()));
+        // Nothing else goes out while the first one is being sent.
+        assert_eq!(outbox.dispatch(secs(1)), None);
+        assert_eq!(outbox.failed(secs(5), true), None);
+        assert_eq!(outbox.deadline(), Some(secs(5 + OUTBOX_RETRY_BASE_SECONDS as u64)));
+        assert_eq!(outbox.dispatch(secs(6)), None);
+
+        assert!(outbox.dispatch(secs(15)).is_some());
+        assert_eq!(outbox.failed(secs(20), true), None);
+        assert_eq!(outbox.deadline(), Some(secs(20 + 2 * OUTBOX_RETRY_BASE_SECONDS as u64)));
+        assert!(outbox.dispatch(secs(40)).is_some());
+        assert_eq!(outbox.sent(), Some(sms("PPP_101_260109231531")));
+
+        assert_eq!(outbox.dispatch(secs(40)), Some(Report::Call("010".try_into().unwrap()).command()));
+        assert!(outbox.sent().is_some());
+        assert!(outbox.is_empty());
+        assert_eq!(outbox.deadline(), None);
+    }
+
+    #[test]
+    fn reports_are_given_up() {
+        let mut outbox = Outbox::new();
+        outbox.push(sms("A"), secs(0));
+        outbox.dispatch(secs(0));
+        assert_eq!(outbox.failed(secs(0), false), Some(sms("A")));
+
+        outbox.push(sms("B"), secs(0));
+        let mut now = secs(0);
+        for _ in 1..OUTBOX_MAX_ATTEMPTS {
+            now = outbox.deadline().unwrap();
+            assert!(outbox.dispatch(now).is_some());
+            assert_eq!(outbox.failed(now, true), None);
+        }
+        assert_eq!(backoff(OUTBOX_MAX_ATTEMPTS), Duration::from_secs(OUTBOX_RETRY_MAX_SECONDS as u64));
+        assert!(outbox.dispatch(outbox.deadline().unwrap()).is_some());
+        assert_eq!(outbox.failed(now, true), Some(sms("B")));
+
+        // No outcome counts as a failure, and old reports expire.
+        outbox.push(sms("C"), secs(0));
+        assert!(outbox.dispatch(secs(0)).is_some());
+        let timeout = secs(OUTBOX_SEND_TIMEOUT_SECONDS as u64);
+        assert_eq!(outbox.deadline(), Some(timeout));
+        assert_eq!(outbox.dispatch(timeout), None);
+        assert_eq!(outbox.dispatch(secs(OUTBOX_EXPIRY_SECONDS as u64)), None);
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn full_outbox_keeps_the_report_being_sent() {
+        let mut outbox = Outbox::new();
+        for code in ["0", "1", "2", "3", "4", "5", "6", "7"].into_iter().take(OUTBOX_SIZE) {
+            outbox.push(Report::Call(code.try_into().unwrap()), secs(0));
+        }
+        outbox.dispatch(secs(0));
+        outbox.push(sms("new"), secs(1));
+        assert_eq!(outbox.len(), OUTBOX_SIZE);
+        assert_eq!(outbox.sent(), Some(Report::Call("0".try_into().unwrap())));
+        assert_eq!(outbox.dispatch(secs(1)), Some(Report::Call("2".try_into().unwrap()).command()));
+    }
+
+    #[test]
+    fn backed_up_reports_are_saved_and_restored() {
+        let mut outbox = Outbox::new();
+        outbox.push(sms("PPP_101_260109231531"), secs(0));
+        // Reports that go out first time never need saving.
+        assert!(!outbox.take_dirty());
+        outbox.dispatch(secs(0));
+        assert!(outbox.is_sending());
+        outbox.failed(secs(1), true);
+        assert!(!outbox.is_sending());
+        outbox.push(Report::Call("777".try_into().unwrap()), secs(2));
+        outbox.resend(sms("PPP_102_260109231531"), 1, secs(3));
+        assert!(outbox.is_dirty());
+        assert!(outbox.take_dirty());
+        assert!(!outbox.is_dirty());
+
+        let mut record = [0xFFu8; Outbox::RECORD_SIZE];
+        let mut written = 0;
+        let res: Result<(), ()> = outbox.save(|offset, bytes| {
+            record[offset..offset + bytes.len()].copy_from_slice(bytes);
+            written += bytes.len();
+            Ok(())
+        });
+        assert_eq!(res, Ok(()));
+        // The unused entry is left erased.
+        assert_eq!(written, Outbox::RECORD_SIZE - ENTRY_SIZE);
+        let mut restored = Outbox::new();
+        let res: Result<(), ()> = restored.load(|offset, buf| {
+            buf.copy_from_slice(&record[offset..offset + buf.len()]);
+            Ok(())
+        }, secs(100));
+        assert_eq!(res, Ok(()));
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored.dispatch(secs(100)), Some(sms("PPP_101_260109231531").command()));
+        restored.sent();
+        assert!(restored.dispatch(secs(100)).is_some());
+        restored.sent();
+        let resent = Command::SendAlarmSms { message: "PPP_102_260109231531".try_into().unwrap(), resends: 1 };
+        assert_eq!(restored.dispatch(secs(100)), Some(resent));
+        restored.sent();
+        // The saved copy still holds the sent report.
+        assert!(restored.take_dirty());
+
+        let mut erased = Outbox::new();
+        let res: Result<(), ()> = erased.load(|_, buf| {
+            buf.fill(0xFF);
+            Ok(())
+        }, secs(0));
+        assert_eq!(res, Ok(()));
+        assert!(erased.is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+// /embassy800c-core/src/phone_book.rs
+use heapless::String;
+
+use crate::constants::MAX_PHONE_LENGTH;
+
+const MAX_PHONE_COUNT: usize = 8;
+
+pub struct PhoneBook {
+    phones: [Option<String<MAX_PHONE_LENGTH>>; MAX_PHONE_COUNT],
+    count: usize,
+}
+
+impl PhoneBook {
+    pub const fn new() -> Self {
+        Self {
+            phones: [None, None, None, None, None, None, None, None],
+            count: 0,
+        }
+    }
+
+    pub fn add_number(&mut self, number: &str) -> Result<(), &'static str> {
+        info!("Trying to add number {}", number);
+        if self.count >= MAX_PHONE_COUNT {
+            return Err("Phone book full");
+        }
+        if number.len() >= MAX_PHONE_LENGTH {
+            return Err("Phone number too long");
+        }
+        if self.contains(number) {
+            return Err("Phone number already exists");
+        }
+
+        let mut s: String<MAX_PHONE_LENGTH> = String::new();
+        if s.push_str(number).is_err() {
+            return Err("Failed to add number");
+        }
+
+        self.phones[self.count] = Some(s);
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn get_first(&self) -> Option<&str> {
+        self.phones.first().and_then(|opt| opt.as_deref())
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        if index < self.count {
+            self.phones[index].as_deref()
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn contains(&self, number: &str) -> bool {
+        self.phones.iter().flatten().any(|entry| entry.as_str() == number)
+    }
+}
+
+impl Default for PhoneBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_insertion_order() {
+        let mut book = PhoneBook::new();
+        assert_eq!(book.get_first(), None);
+        book.add_number("+79001234567").unwrap();
+        book.add_number("+79007654321").unwrap();
+        assert_eq!(book.get_first(), Some("+79001234567"));
+        assert_eq!(book.get(1), Some("+79007654321"));
+        assert_eq!(book.get(2), None);
+    }
+
+    #[test]
+    fn rejects_duplicates_and_overflow() {
+        let mut book = PhoneBook::new();
+        book.add_number("100").unwrap();
+        assert!(book.add_number("100").is_err());
+        assert!(book.add_number("+7900123456789012").is_err());
+
+        for i in 1..MAX_PHONE_COUNT {
+            let mut s = String::<MAX_PHONE_LENGTH>::new();
+            use core::fmt::Write;
+            write!(s, "{}", 200 + i).unwrap();
+            book.add_number(&s).unwrap();
+        }
+        assert!(book.add_number("999").is_err());
+    }
+
+    #[test]
+    fn clear_allows_reload() {
+        let mut book = PhoneBook::new();
+        book.add_number("100").unwrap();
+        book.clear();
+        assert_eq!(book.get_first(), None);
+        book.add_number("100").unwrap();
+        assert_eq!(book.get(0), Some("100"));
+    }
+}
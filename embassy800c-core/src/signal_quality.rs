@@ -0,0 +1,145 @@
+// /embassy800c-core/src/signal_quality.rs
+//! Signal quality reported by `AT+CSQ` and its statistics since boot.
+
+use core::fmt;
+
+/// One `+CSQ: <rssi>,<ber>` reading.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SignalQuality {
+    pub rssi: u8,
+    pub ber: u8,
+}
+
+impl SignalQuality {
+    /// Received signal strength in dBm, `None` when unknown (99).
+    ///
+    /// 0 stands for -113 dBm or less and 31 for -51 dBm or more.
+    pub fn dbm(&self) -> Option<i16> {
+        match self.rssi {
+            rssi @ 0..=31 => Some(-113 + 2 * rssi as i16),
+            _ => None,
+        }
+    }
+
+    /// Bit error rate class (RXQUAL 0..=7, below 0.2 % up to above 12.8 %),
+    /// `None` when unknown (99), e.g. outside a call.
+    pub fn ber(&self) -> Option<u8> {
+        match self.ber {
+            ber @ 0..=7 => Some(ber),
+            _ => None,
+        }
+    }
+}
+
+/// Minimum, average and maximum of the known signal strengths.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SignalStats {
+    min: i16,
+    max: i16,
+    sum: i32,
+    count: u16,
+}
+
+impl SignalStats {
+    pub const fn new() -> Self {
+        Self { min: 0, max: 0, sum: 0, count: 0 }
+    }
+
+    pub fn record(&mut self, dbm: i16) {
+        if self.count == 0 || dbm < self.min {
+            self.min = dbm;
+        }
+        if self.count == 0 || dbm > self.max {
+            self.max = dbm;
+        }
+        // Halve the history instead of overflowing, the average stays the same.
+        if self.count == u16::MAX {
+            self.sum /= 2;
+            self.count /= 2;
+        }
+        self.sum += dbm as i32;
+        self.count += 1;
+    }
+
+    pub fn min(&self) -> Option<i16> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<i16> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn average(&self) -> Option<i16> {
+        (self.count > 0).then(|| (self.sum / self.count as i32) as i16)
+    }
+}
+
+/// Latest reading with the statistics including it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SignalReport {
+    pub quality: SignalQuality,
+    pub stats: SignalStats,
+}
+
+/// `current/min/avg/max` in dBm, `?` for unknown values.
+impl fmt::Display for SignalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values = [self.quality.dbm(), self.stats.min(), self.stats.average(), self.stats.max()];
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+            match value {
+                Some(dbm) => write!(f, "{}", dbm)?,
+                None => f.write_str("?")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn rssi_is_converted_to_dbm() {
+        assert_eq!(SignalQuality { rssi: 0, ber: 0 }.dbm(), Some(-113));
+        assert_eq!(SignalQuality { rssi: 1, ber: 0 }.dbm(), Some(-111));
+        assert_eq!(SignalQuality { rssi: 20, ber: 0 }.dbm(), Some(-73));
+        assert_eq!(SignalQuality { rssi: 31, ber: 0 }.dbm(), Some(-51));
+        assert_eq!(SignalQuality { rssi: 99, ber: 99 }.dbm(), None);
+        assert_eq!(SignalQuality { rssi: 99, ber: 99 }.ber(), None);
+        assert_eq!(SignalQuality { rssi: 10, ber: 7 }.ber(), Some(7));
+    }
+
+    #[test]
+    fn stats_track_min_avg_max() {
+        let mut stats = SignalStats::new();
+        assert_eq!(stats.average(), None);
+        for dbm in [-73, -95, -61] {
+            stats.record(dbm);
+        }
+        assert_eq!(stats.min(), Some(-95));
+        assert_eq!(stats.average(), Some(-76));
+        assert_eq!(stats.max(), Some(-61));
+    }
+
+    #[test]
+    fn report_formatting() {
+        let mut stats = SignalStats::new();
+        let mut out = heapless::String::<32>::new();
+        write!(out, "{}", SignalReport { quality: SignalQuality { rssi: 99, ber: 99 }, stats }).unwrap();
+        assert_eq!(out.as_str(), "?/?/?/?");
+
+        stats.record(-73);
+        stats.record(-81);
+        out.clear();
+        write!(out, "{}", SignalReport { quality: SignalQuality { rssi: 20, ber: 0 }, stats }).unwrap();
+        assert_eq!(out.as_str(), "-73/-81/-77/-73");
+    }
+}
@@ -0,0 +1,2449 @@
+// /embassy800c-core/src/sim800.rs
+use embassy_time::{Duration, Instant, with_deadline, with_timeout, TimeoutError, Timer};
+use embedded_io_async::{Read, Write};
+use heapless::{Deque, String, Vec};
+
+use crate::at_parser::{parse_line, FinalResult, Info, RegistrationStatus, Response, SimStatus, Urc};
+use crate::constants::*;
+use crate::custom_strings::{decode_ucs2_hex, separate_chars_by_commas};
+use crate::gsm_time_converter::GsmTime;
+use crate::modem_wake::ModemWake;
+use crate::phone_book::PhoneBook;
+use crate::signal_quality::{SignalQuality, SignalReport, SignalStats};
+use crate::sms_assembler::SmsAssembler;
+use crate::sms_pdu::{self, Concat, Pdu, PduError};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::{Sender, Receiver};
+
+/// Control lines of the SIM800 module that are not part of the UART.
+#[allow(async_fn_in_trait)]
+pub trait PowerControl {
+    /// Drives the PWRKEY transistor; a pulse of at least a second switches
+    /// the module on or off.
+    fn set_enable(&mut self, high: bool);
+    /// Drives the TTL level shifter enable.
+    fn set_ttl(&mut self, high: bool);
+    /// Switches the UART link to GSM 07.10 framing once the module accepted
+    /// `AT+CMUX`, and back to plain when it is switched off or rebooted.
+    fn set_multiplexed(&mut self, _on: bool) {}
+    /// Whether the link is framed, i.e. the channels opened after
+    /// `set_multiplexed(true)` rather than falling back to plain.
+    fn is_multiplexed(&self) -> bool {
+        false
+    }
+    /// Drives DTR; while it is high a module set to `AT+CSCLK=1` sleeps.
+    fn set_dtr(&mut self, _high: bool) {}
+    /// Resolves when the module is to be woken from outside the driver,
+    /// e.g. because its ring indicator announced a call or SMS.
+    async fn wake_requested(&mut self) {
+        core::future::pending().await
+    }
+    /// Whether another AT channel is using the module, which must then not
+    /// be put to sleep.
+    fn keep_awake(&self) -> bool {
+        false
+    }
+    /// Reconfigures the MCU side of the UART to `baud`.
+    fn set_baud_rate(&mut self, _baud: u32) {}
+}
+
+// Types for communication
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+    Init,
+    SendMessage {
+        phone_number: String<MAX_PHONE_LENGTH>,
+        message: String<SMS_TEXT_LENGTH>,
+    },
+    SendAlarmSms {
+        message: String<SMS_TEXT_LENGTH>,
+        /// Times the report was sent again after it was not delivered,
+        /// handed back with its `SmsDelivery`.
+        resends: u8,
+    },
+    CallWithDtmf {
+        phone_number: String<MAX_PHONE_LENGTH>,
+        dtmf: String<DTMF_PACKET_LENGTH>,
+    },
+    CallAlarmWithDtmf {
+        dtmf: String<DTMF_PACKET_LENGTH>,
+    },
+    HandleIncomingCall {
+        phone_number: String<MAX_PHONE_LENGTH>,
+    },
+    UpdateTime,
+    /// Graceful `AT+CPOWD=1` shutdown; `Init` switches the module on again.
+    PowerOff,
+    /// Answered by `Identity` with the values read at power-on.
+    QueryIdentity,
+    /// USSD request such as `*100#`, answered by `UssdResponse`.
+    Ussd {
+        code: String<USSD_CODE_LENGTH>,
+    },
+}
+
+impl Command {
+    /// Outgoing calls and SMS, held back while the module is not registered.
+    fn needs_network(&self) -> bool {
+        matches!(
+            self,
+            Command::SendMessage { .. }
+                | Command::SendAlarmSms { .. }
+                | Command::CallWithDtmf { .. }
+                | Command::CallAlarmWithDtmf { .. }
+                | Command::Ussd { .. }
+        )
+    }
+
+    /// Result event reporting that the command could not be carried out.
+    fn failed(&self, err: Sim800Error) -> Option<SimEvent> {
+        match self {
+            Command::SendMessage { .. } | Command::SendAlarmSms { .. } => Some(SimEvent::SmsSent(Err(err))),
+            Command::CallAlarmWithDtmf { .. } => Some(SimEvent::CallExecuted(Err(err))),
+            Command::Ussd { .. } => Some(SimEvent::UssdResponse(Err(err))),
+            _ => None,
+        }
+    }
+}
+
+// Events generated by SIM800 (URCs)
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SimEvent {
+    SmsReceived {
+        number: String<MAX_PHONE_LENGTH>,
+        message: String<SMS_TEXT_LENGTH>,
+    },
+    CallReceived {
+        number: String<MAX_PHONE_LENGTH>,
+    },
+    DtmfReceived(char),
+    CallEnded,
+    /// Outcome of an alarm call; `Ok` once the remote confirmed it.
+    CallExecuted(Result<(), Sim800Error>),
+    /// Outcome of `SendMessage` / `SendAlarmSms`.
+    SmsSent(Result<(), Sim800Error>),
+    TimeReceived(GsmTime),
+    /// Received data was lost or garbled; the affected line was dropped.
+    ReceiveError(Sim800Error),
+    /// The module was power-cycled and initialised again.
+    ModemRestarted(RestartReason),
+    /// Power-on completed, the module is ready for calls and SMS.
+    ModemReady,
+    /// The power-on sequence stopped at this stage (other than the SIM).
+    PowerOnFailed(PowerOnStage),
+    /// The SIM is missing, locked or blocked.
+    SimFault(SimFault),
+    /// Network registration changed (`+CREG`).
+    NetworkStatus(RegistrationStatus),
+    /// Reading of the periodic `AT+CSQ` poll.
+    SignalQuality(SignalReport),
+    /// Module supply voltage in mV from the periodic `AT+CBC` poll.
+    SupplyVoltage(u16),
+    /// Answer to `Command::QueryIdentity`.
+    Identity(ModemIdentity),
+    /// The module entered or left slow-clock sleep.
+    PowerState(ModemPowerState),
+    /// No ICCID was provisioned; this SIM is the expected one from now on
+    /// and should be persisted.
+    SimProvisioned(String<ICCID_LENGTH>),
+    /// The SIM is not the provisioned one, reported on every modem start.
+    SimChanged {
+        expected: String<ICCID_LENGTH>,
+        found: String<ICCID_LENGTH>,
+    },
+    /// Answer of the network to `Command::Ussd`.
+    UssdResponse(Result<String<SIM800_LINE_BUFFER_SIZE>, Sim800Error>),
+    /// Not registered for longer than the registration timeout; deferred
+    /// calls and SMS have been failed with `NotRegistered`.
+    NetworkLost,
+    /// Final status report of a `SendMessage` / `SendAlarmSms` that went
+    /// out; the command is handed back so it can be sent again.
+    SmsDelivery {
+        command: Command,
+        status: DeliveryStatus,
+    },
+}
+
+impl SimEvent {
+    /// Reports that a later one supersedes or that only feed statistics,
+    /// given up first when the event queue is full.
+    fn droppable(&self) -> bool {
+        matches!(
+            self,
+            SimEvent::ReceiveError(_)
+                | SimEvent::NetworkStatus(_)
+                | SimEvent::SignalQuality(_)
+                | SimEvent::SupplyVoltage(_)
+                | SimEvent::PowerState(_)
+        )
+    }
+}
+
+/// What the network reported about a sent SMS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeliveryStatus {
+    /// Every part reached the recipient.
+    Delivered,
+    /// The service centre gave up, with the TP-Status of the report.
+    Failed(u8),
+    /// No final report within `SMS_DELIVERY_TIMEOUT_SECONDS`.
+    Unconfirmed,
+}
+
+/// A sent SMS waiting for the status reports of its parts.
+struct SentSms {
+    command: Command,
+    /// `+CMGS` references of the parts not yet delivered.
+    references: Vec<u8, SMS_MAX_PARTS>,
+    sent: Instant,
+}
+
+/// A `+CDS` status report; in PDU mode the report follows on the next line.
+#[derive(Clone, Copy)]
+enum StatusUrc {
+    Text { reference: u8, status: u8 },
+    Pdu,
+}
+
+impl StatusUrc {
+    fn from_response(response: &Response<'_>) -> Option<Self> {
+        match *response {
+            Response::Urc(Urc::Cds { reference, status }) => Some(StatusUrc::Text { reference, status }),
+            Response::Urc(Urc::CdsPdu { .. }) => Some(StatusUrc::Pdu),
+            _ => None,
+        }
+    }
+}
+
+/// Module and SIM identification read during power-on; empty where the
+/// module did not answer.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ModemIdentity {
+    /// `AT+GSN`
+    pub imei: String<16>,
+    /// `AT+CCID`, without the `F` padding some SIMs report.
+    pub iccid: String<ICCID_LENGTH>,
+    /// `AT+CIMI`
+    pub imsi: String<16>,
+    /// `AT+GMR`, e.g. `1418B05SIM800C24`.
+    pub revision: String<24>,
+}
+
+/// Power state of the module as driven by the driver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ModemPowerState {
+    Off,
+    Awake,
+    /// Slow-clock sleep (`AT+CSCLK=1`, DTR high); URCs still wake it.
+    Sleeping,
+}
+
+/// Why the supervisor power-cycled the module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RestartReason {
+    /// `SIM800_MAX_PING_FAILURES` pings in a row failed; holds the last error.
+    Unresponsive(Sim800Error),
+    /// The module sent `RDY` on its own, e.g. after a brown-out.
+    Rebooted,
+}
+
+/// Stage of the power-on sequence that did not complete in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerOnStage {
+    /// Neither `RDY` nor an answer to `AT` after the PWRKEY pulse.
+    Boot,
+    /// `+CFUN: 1` (full functionality) missing.
+    Functionality,
+    /// `+CPIN: READY` missing.
+    Sim(SimFault),
+    /// `Call Ready` missing.
+    CallReady,
+    /// `SMS Ready` missing.
+    SmsReady,
+}
+
+/// Why the SIM is not usable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SimFault {
+    NotInserted,
+    /// PIN locked and no PIN is configured.
+    PinRequired,
+    /// The configured PIN was refused; it is not tried again until reset.
+    PinRejected,
+    /// Only one PIN attempt is left, using it up would block the SIM.
+    LastAttempt,
+    /// Blocked, the PUK has to be entered in a phone.
+    PukRequired,
+    /// `+CPIN: READY` did not arrive in time.
+    NotReady,
+}
+
+/// Readiness reported by the module since the last PWRKEY pulse.
+#[derive(Default)]
+struct Readiness {
+    functional: bool,
+    sim: Option<SimStatus>,
+    call: bool,
+    sms: bool,
+}
+
+impl Readiness {
+    fn update(&mut self, response: &Response<'_>) {
+        match *response {
+            Response::Urc(Urc::Cfun(fun)) => self.functional = fun == 1,
+            Response::Urc(Urc::Cpin(status)) => self.sim = Some(status),
+            Response::Urc(Urc::CallReady) | Response::Info(Info::Ccalr(true)) => self.call = true,
+            Response::Urc(Urc::SmsReady) => self.sms = true,
+            _ => {}
+        }
+    }
+
+    /// A missing or locked SIM will not become ready by waiting.
+    fn sim_fault(&self) -> Option<SimFault> {
+        match self.sim? {
+            SimStatus::NotInserted => Some(SimFault::NotInserted),
+            SimStatus::PinRequired => Some(SimFault::PinRequired),
+            SimStatus::PukRequired => Some(SimFault::PukRequired),
+            _ => None,
+        }
+    }
+
+    /// First stage still missing, in boot order.
+    fn missing(&self) -> Option<PowerOnStage> {
+        if !self.functional {
+            Some(PowerOnStage::Functionality)
+        } else if self.sim != Some(SimStatus::Ready) {
+            Some(PowerOnStage::Sim(SimFault::NotReady))
+        } else if !self.call {
+            Some(PowerOnStage::CallReady)
+        } else if !self.sms {
+            Some(PowerOnStage::SmsReady)
+        } else {
+            None
+        }
+    }
+}
+
+/// Why a SIM800 operation failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Sim800Error {
+    /// No final result within the command timeout.
+    Timeout,
+    /// The `>` prompt for the SMS text did not arrive.
+    NoPrompt,
+    /// Plain `ERROR` (or a verbose error text without a code).
+    Error,
+    /// `+CME ERROR: <code>` (equipment / SIM / network).
+    Cme(u16),
+    /// `+CMS ERROR: <code>` (SMS service).
+    Cms(u16),
+    NoCarrier,
+    Busy,
+    NoAnswer,
+    NoDialtone,
+    /// The call was answered but the remote did not confirm the DTMF packet.
+    Unconfirmed,
+    /// There is no phone book entry to send an alarm to.
+    NoRecipient,
+    /// A line did not fit into the line buffer.
+    LineOverflow,
+    /// The UART reported an error, e.g. its DMA ring buffer overran.
+    RxOverrun,
+    /// A line was not valid UTF-8.
+    Utf8,
+    /// The receiver reached end of stream.
+    Closed,
+    /// The module has not been registered with the network for too long.
+    NotRegistered,
+    /// The network ended the USSD session without an answer (`+CUSD: 4`).
+    UssdRejected,
+    /// An SMS PDU could not be built or a received one not decoded.
+    Pdu(PduError),
+}
+
+impl Sim800Error {
+    /// SIM missing, locked or broken (`+CME ERROR: 10..=18`, `+CMS ERROR: 310..=317`).
+    pub fn is_sim_failure(&self) -> bool {
+        matches!(*self, Sim800Error::Cme(10..=18) | Sim800Error::Cms(310..=317))
+    }
+
+    /// Transient network trouble worth retrying later.
+    pub fn is_network_failure(&self) -> bool {
+        matches!(
+            *self,
+            Sim800Error::Busy
+                | Sim800Error::NoCarrier
+                | Sim800Error::NoDialtone
+                | Sim800Error::NotRegistered
+                | Sim800Error::Cme(30..=32)
+                | Sim800Error::Cms(331 | 332)
+        )
+    }
+}
+
+impl From<TimeoutError> for Sim800Error {
+    fn from(_: TimeoutError) -> Self {
+        Sim800Error::Timeout
+    }
+}
+
+/// Maps a final result code to the outcome of the command it terminates.
+fn final_result(result: FinalResult) -> Result<(), Sim800Error> {
+    match result {
+        FinalResult::Ok => Ok(()),
+        FinalResult::Error => Err(Sim800Error::Error),
+        FinalResult::CmeError(code) => Err(Sim800Error::Cme(code)),
+        FinalResult::CmsError(code) => Err(Sim800Error::Cms(code)),
+        FinalResult::NoCarrier => Err(Sim800Error::NoCarrier),
+        FinalResult::Busy => Err(Sim800Error::Busy),
+        FinalResult::NoAnswer => Err(Sim800Error::NoAnswer),
+        FinalResult::NoDialtone => Err(Sim800Error::NoDialtone),
+    }
+}
+
+/// Splits the received bytes into lines. A partial line is kept across
+/// cancelled reads.
+struct LineReader<R> {
+    rx: R,
+    rx_buf: [u8; SIM800_RX_CHUNK_SIZE],
+    rx_pos: usize,
+    rx_len: usize,
+    line_buf: [u8; SIM800_MAX_LINE_LENGTH],
+    /// Length of the last complete line.
+    line_len: usize,
+    /// Bytes collected so far for the next line.
+    line_pos: usize,
+    /// Skipping the rest of a line that overflowed or lost bytes.
+    line_discard: bool,
+}
+
+impl<R: Read> LineReader<R> {
+    fn new(rx: R) -> Self {
+        Self {
+            rx,
+            rx_buf: [0u8; SIM800_RX_CHUNK_SIZE],
+            rx_pos: 0,
+            rx_len: 0,
+            line_buf: [0u8; SIM800_MAX_LINE_LENGTH],
+            line_len: 0,
+            line_pos: 0,
+            line_discard: false,
+        }
+    }
+
+    /// Reads the next line into `line`; with `prompt` set, a `>` at the
+    /// start of a line is returned on its own since the SMS text prompt has
+    /// no line ending.
+    ///
+    /// Lines longer than the line buffer, lines hit by a receiver error and
+    /// lines that are not UTF-8 are dropped whole. Each is reported as an
+    /// error once; reading on continues behind it.
+    async fn read(&mut self, prompt: bool) -> Result<(), Sim800Error> {
+        loop {
+            if self.rx_pos == self.rx_len {
+                match self.rx.read(&mut self.rx_buf).await {
+                    Ok(0) => return Err(Sim800Error::Closed),
+                    Ok(n) => {
+                        self.rx_pos = 0;
+                        self.rx_len = n;
+                    }
+                    Err(_) => {
+                        warn!("SIM800 receiver error, dropping line");
+                        return Err(self.drop_line(Sim800Error::RxOverrun));
+                    }
+                }
+            }
+            let b = self.rx_buf[self.rx_pos];
+            self.rx_pos += 1;
+
+            if prompt && self.line_pos == 0 && !self.line_discard && b == b'>' {
+                self.line_buf[0] = b;
+                self.line_len = 1;
+                return Ok(());
+            }
+            match b {
+                b'\n' => {
+                    let pos = self.line_pos;
+                    self.line_pos = 0;
+                    if self.line_discard {
+                        self.line_discard = false;
+                        continue;
+                    }
+                    // Line noise must not abort whoever is waiting for a reply.
+                    if core::str::from_utf8(&self.line_buf[..pos]).is_err() {
+                        warn!("Dropping non UTF-8 line");
+                        return Err(Sim800Error::Utf8);
+                    }
+                    self.line_len = pos;
+                    return Ok(());
+                }
+                b'\r' => {}
+                _ if self.line_discard => {}
+                _ if self.line_pos == self.line_buf.len() => {
+                    warn!("SIM800 line too long, dropping it");
+                    return Err(self.drop_line(Sim800Error::LineOverflow));
+                }
+                _ => {
+                    self.line_buf[self.line_pos] = b;
+                    self.line_pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Discards the line being received up to its terminating newline.
+    fn drop_line(&mut self, reason: Sim800Error) -> Sim800Error {
+        self.line_pos = 0;
+        self.line_discard = true;
+        reason
+    }
+
+    /// The last line read.
+    fn line(&self) -> &str {
+        core::str::from_utf8(&self.line_buf[..self.line_len]).unwrap_or("")
+    }
+
+    /// Forgets whatever was received and not read yet.
+    fn discard(&mut self) {
+        self.rx_pos = self.rx_len;
+        self.line_pos = 0;
+        self.line_discard = false;
+    }
+}
+
+/// Command and response exchange on an AT channel, shared by `Sim800Driver`
+/// and `QueryChannel`.
+trait AtChannel {
+    async fn send_str(&mut self, s: &str);
+
+    /// Reads the next response that is not an unsolicited result the
+    /// channel deals with itself.
+    async fn read_response(&mut self) -> Result<Response<'_>, Sim800Error>;
+
+    async fn send_cmd_wait_ok(&mut self, cmd: &str, timeout_ms: u64) -> Result<(), Sim800Error> {
+        self.send_cmd_with(cmd, timeout_ms, |_| {}).await
+    }
+
+    /// Like `send_cmd_wait_ok`, handing every line before the final result to `on_info`.
+    async fn send_cmd_with(
+        &mut self,
+        cmd: &str,
+        timeout_ms: u64,
+        mut on_info: impl FnMut(&Response<'_>),
+    ) -> Result<(), Sim800Error> {
+        self.send_str(cmd).await;
+        self.send_str("\r\n").await;
+        
+        with_timeout(Duration::from_millis(timeout_ms), async {
+            loop {
+                match self.read_response().await? {
+                    Response::Final(result) => return final_result(result),
+                    other => on_info(&other),
+                }
+            }
+        }).await?
+    }
+
+    // Specialized handler for UpdateTime to ensure +CCLK is parsed
+    async fn execute_update_time(&mut self) -> Result<GsmTime, Sim800Error> {
+        self.send_str("AT+CCLK?").await;
+        self.send_str("\r\n").await;
+        
+        let mut found_time = None;
+
+        with_timeout(Duration::from_secs(2), async {
+            loop {
+                match self.read_response().await? {
+                    Response::Final(result) => return final_result(result),
+                    Response::Info(Info::Cclk(time)) => found_time = Some(time),
+                    _ => {}
+                }
+            }
+        }).await??;
+
+        // OK without +CCLK is as good as no reply.
+        found_time.ok_or(Sim800Error::Timeout)
+    }
+
+    /// Sends a USSD request and returns the network's answer.
+    async fn send_ussd(&mut self, code: &str) -> Result<String<SIM800_LINE_BUFFER_SIZE>, Sim800Error> {
+        let mut cmd = String::<32>::new();
+        use core::fmt::Write;
+        let _ = write!(cmd, "AT+CUSD=1,\"{}\"", code);
+
+        // The answer usually follows the OK, but may also come before it.
+        let mut reply = None;
+        self.send_cmd_with(&cmd, 2000, |r| {
+            if let Response::Urc(Urc::Cusd { status, text, dcs }) = *r {
+                reply = Some((status, ussd_text(text, dcs)));
+            }
+        }).await?;
+        let (status, text) = match reply {
+            Some(reply) => reply,
+            None => with_timeout(Duration::from_secs(SIM800_USSD_TIMEOUT_SECONDS as u64), async {
+                loop {
+                    if let Response::Urc(Urc::Cusd { status, text, dcs }) = self.read_response().await? {
+                        return Ok::<_, Sim800Error>((status, ussd_text(text, dcs)));
+                    }
+                }
+            }).await??,
+        };
+
+        // The network expects a menu choice; end the session instead.
+        if status == 1 {
+            self.send_cmd_wait_ok("AT+CUSD=2", 1000).await.ok();
+        }
+        text
+    }
+}
+
+pub struct Sim800Driver<T, R, P> {
+    tx: T,
+    lines: LineReader<R>,
+    control: P,
+    phone_book: PhoneBook,
+    pending_events: Deque<SimEvent, MAXIMUM_PENDING_EVENTS>,
+    last_alarm_dtmf: String<DTMF_PACKET_LENGTH>,
+    last_alarm_time: u64,
+    ping_period: Duration,
+    ping_failures: u8,
+    /// The module is meant to be on; the supervisor leaves it alone otherwise.
+    powered: bool,
+    sim_pin: Option<&'static str>,
+    pin_rejected: bool,
+    /// Last `+CREG` state, `None` until reported after power-on.
+    registration: Option<RegistrationStatus>,
+    registration_timeout: Duration,
+    /// Start of the current registration loss.
+    lost_since: Option<Instant>,
+    loss_reported: bool,
+    /// Calls and SMS waiting for network registration.
+    deferred: Deque<Command, MAXIMUM_DEFERRED_COMMANDS>,
+    signal: SignalStats,
+    identity: ModemIdentity,
+    /// ICCID of the SIM the unit was set up with.
+    provisioned_iccid: Option<String<ICCID_LENGTH>>,
+    /// Start the GSM 07.10 multiplexer during configuration.
+    cmux: bool,
+    /// Idle time after which the module is put to sleep, `None` to keep it awake.
+    sleep_idle: Option<Duration>,
+    power_state: ModemPowerState,
+    last_activity: Instant,
+    /// Rate the module is detected at and locked to, `None` to leave the UART as it is.
+    baud_rate: Option<u32>,
+    /// SMS are exchanged as PDUs (`AT+CMGF=0`) instead of text.
+    pdu_mode: bool,
+    /// Parts of incoming concatenated SMS.
+    assembler: SmsAssembler,
+    /// Reference of the last concatenated SMS sent.
+    concat_reference: u8,
+    /// `+CMGS` references of the SMS being sent.
+    sent_references: Vec<u8, SMS_MAX_PARTS>,
+    /// Sent SMS awaiting their status reports, oldest first.
+    sent_sms: Vec<SentSms, MAXIMUM_TRACKED_SMS>,
+    /// SMS are left on the SIM for `sweep_stored_sms`.
+    stored_sms: bool,
+}
+
+impl<T: Write, R: Read, P: PowerControl> Sim800Driver<T, R, P> {
+    pub fn new(tx: T, rx: R, control: P) -> Self {
+        Self {
+            tx,
+            lines: LineReader::new(rx),
+            control,
+            phone_book: PhoneBook::new(),
+            pending_events: Deque::new(),
+            last_alarm_dtmf: String::new(),
+            last_alarm_time: 0,
+            ping_period: Duration::from_secs(SIM800_PING_PERIOD_SECONDS as u64),
+            ping_failures: 0,
+            powered: false,
+            sim_pin: None,
+            pin_rejected: false,
+            registration: None,
+            registration_timeout: Duration::from_secs(SIM800_REGISTRATION_TIMEOUT_SECONDS as u64),
+            lost_since: None,
+            loss_reported: false,
+            deferred: Deque::new(),
+            signal: SignalStats::new(),
+            identity: ModemIdentity::default(),
+            provisioned_iccid: None,
+            cmux: false,
+            sleep_idle: None,
+            power_state: ModemPowerState::Off,
+            last_activity: Instant::now(),
+            baud_rate: None,
+            pdu_mode: false,
+            assembler: SmsAssembler::new(),
+            concat_reference: 0,
+            sent_references: Vec::new(),
+            sent_sms: Vec::new(),
+            stored_sms: false,
+        }
+    }
+
+    /// PIN entered when the SIM asks for it during power-on.
+    pub fn set_sim_pin(&mut self, pin: Option<&'static str>) {
+        self.sim_pin = pin;
+    }
+
+    /// How often `run` checks that the module still answers `AT`.
+    pub fn set_ping_period(&mut self, period: Duration) {
+        self.ping_period = period;
+    }
+
+    /// How long the module may stay unregistered before `NetworkLost` is reported.
+    pub fn set_registration_timeout(&mut self, timeout: Duration) {
+        self.registration_timeout = timeout;
+    }
+
+    /// Whether the module is registered with its home or a roaming network.
+    pub fn is_registered(&self) -> bool {
+        self.registration.is_some_and(|status| status.is_registered())
+    }
+
+    /// ICCID the SIM is checked against on every modem start, `None` to
+    /// adopt the next SIM read (reported by `SimProvisioned`).
+    pub fn set_provisioned_iccid(&mut self, iccid: Option<String<ICCID_LENGTH>>) {
+        self.provisioned_iccid = iccid;
+    }
+
+    /// Sends `AT+CMUX=0` after every power-on, so that the driver talks on
+    /// the AT channel of a multiplexer (see `PowerControl::set_multiplexed`).
+    pub fn set_cmux(&mut self, enabled: bool) {
+        self.cmux = enabled;
+    }
+
+    /// Lets the module sleep (`AT+CSCLK=1`) once nothing was sent or
+    /// received for `idle`; it is woken with DTR before the next command.
+    pub fn set_sleep_idle(&mut self, idle: Option<Duration>) {
+        self.sleep_idle = idle;
+    }
+
+    /// Probes `SIM800_BAUD_RATES` when the module does not answer after
+    /// a power-on, then fixes it at `baud` with `AT+IPR` and `AT&W`. The
+    /// UART is expected to start at `baud`.
+    pub fn set_baud_rate(&mut self, baud: Option<u32>) {
+        self.baud_rate = baud;
+    }
+
+    /// Sends and receives SMS as PDUs (`AT+CMGF=0`), so that texts outside
+    /// the GSM alphabet (e.g. Cyrillic) go out as UCS2 and arrive intact,
+    /// and long texts are sent and received as concatenated SMS.
+    pub fn set_pdu_mode(&mut self, enabled: bool) {
+        self.pdu_mode = enabled;
+    }
+
+    pub fn power_state(&self) -> ModemPowerState {
+        self.power_state
+    }
+
+    /// IMEI, ICCID, IMSI and firmware revision read by `power_on`.
+    pub fn identity(&self) -> &ModemIdentity {
+        &self.identity
+    }
+
+    /// Numbers loaded from the SIM phonebook by `power_on`.
+    pub fn phone_book(&self) -> &PhoneBook {
+        &self.phone_book
+    }
+
+    async fn read_line(&mut self) -> Result<&str, Sim800Error> {
+        self.read_frame(false).await
+    }
+
+    /// Reads one line, see `LineReader::read`; dropped lines are reported
+    /// as `SimEvent::ReceiveError`.
+    async fn read_frame(&mut self, prompt: bool) -> Result<&str, Sim800Error> {
+        loop {
+            match self.lines.read(prompt).await {
+                Ok(()) => return Ok(self.lines.line()),
+                Err(Sim800Error::Closed) => return Err(Sim800Error::Closed),
+                Err(e) => self.queue_event(SimEvent::ReceiveError(e)),
+            }
+        }
+    }
+
+    async fn read_response_frame(&mut self, prompt: bool) -> Result<Response<'_>, Sim800Error> {
+        loop {
+            let response = parse_line(self.read_frame(prompt).await?);
+            if let Some(report) = StatusUrc::from_response(&response) {
+                self.receive_status_report(report).await;
+                continue;
+            }
+            if let Response::Urc(Urc::Cmti { .. }) = response {
+                self.stored_sms = true;
+                continue;
+            }
+            let Some(event) = Self::urc_event(&response) else {
+                break;
+            };
+            self.defer_event(event).await;
+        }
+        Ok(parse_line(self.lines.line()))
+    }
+
+    /// Event for URCs that must reach the application whatever the driver is doing.
+    fn urc_event(response: &Response<'_>) -> Option<SimEvent> {
+        match *response {
+            Response::Urc(Urc::Cmt { sender, .. }) => Some(SimEvent::SmsReceived {
+                number: truncated(sender),
+                message: String::new(),
+            }),
+            // The sender is part of the PDU on the next line.
+            Response::Urc(Urc::CmtPdu { .. }) => Some(SimEvent::SmsReceived {
+                number: String::new(),
+                message: String::new(),
+            }),
+            Response::Urc(Urc::Clip { number, .. }) => Some(SimEvent::CallReceived {
+                number: truncated(number),
+            }),
+            Response::Urc(Urc::Creg { status, .. }) => Some(SimEvent::NetworkStatus(status)),
+            _ => None,
+        }
+    }
+
+    /// Queues an event for `run` to forward, completing SMS events with the
+    /// message text (or PDU) that follows the `+CMT` header line. Returns
+    /// whether the event was taken, either queued or held back as one part
+    /// of a longer SMS, so that a stored SMS that failed to read is kept.
+    async fn defer_event(&mut self, event: SimEvent) -> bool {
+        let event = match event {
+            SimEvent::SmsReceived { .. } if self.pdu_mode => {
+                let pdu = self.read_line().await.map(sms_pdu::decode::<SMS_TEXT_LENGTH>);
+                match pdu {
+                    Ok(Ok(Pdu::Deliver(sms))) => {
+                        let Some(concat) = sms.concat else {
+                            self.queue_event(SimEvent::SmsReceived { number: sms.originator, message: sms.text });
+                            return true;
+                        };
+                        let Some(sms) = self.assembler.push(&sms.originator, concat, &sms.text, Instant::now()) else {
+                            return true;
+                        };
+                        SimEvent::SmsReceived { number: sms.sender, message: sms.text }
+                    }
+                    Ok(Ok(Pdu::StatusReport(_))) => return true,
+                    Ok(Err(e)) => {
+                        self.queue_event(SimEvent::ReceiveError(Sim800Error::Pdu(e)));
+                        return false;
+                    }
+                    Err(e) => {
+                        self.queue_event(SimEvent::ReceiveError(e));
+                        return false;
+                    }
+                }
+            }
+            SimEvent::SmsReceived { number, .. } => match self.read_line().await {
+                Ok(line) => SimEvent::SmsReceived { number, message: truncated(line) },
+                Err(e) => {
+                    self.queue_event(SimEvent::ReceiveError(e));
+                    return false;
+                }
+            },
+            SimEvent::NetworkStatus(status) => {
+                if !self.track_registration(status) {
+                    return true;
+                }
+                SimEvent::NetworkStatus(status)
+            }
+            other => other,
+        };
+        self.queue_event(event);
+        true
+    }
+
+    /// Reads a status report and matches it to the SMS it is about.
+    async fn receive_status_report(&mut self, report: StatusUrc) {
+        let (reference, status) = match report {
+            StatusUrc::Text { reference, status } => (reference, status),
+            StatusUrc::Pdu => match self.read_line().await.map(sms_pdu::decode::<0>) {
+                Ok(Ok(Pdu::StatusReport(report))) => (report.reference, report.status),
+                Ok(Ok(Pdu::Deliver(_))) => return,
+                Ok(Err(e)) => return self.queue_event(SimEvent::ReceiveError(Sim800Error::Pdu(e))),
+                Err(e) => return self.queue_event(SimEvent::ReceiveError(e)),
+            },
+        };
+        self.track_delivery(reference, status);
+    }
+
+    /// Starts waiting for the status reports of an SMS just sent.
+    fn track_sms(&mut self, command: Command) {
+        let references = core::mem::take(&mut self.sent_references);
+        if references.is_empty() {
+            return;
+        }
+        if self.sent_sms.is_full() {
+            warn!("Too many SMS awaiting status reports, forgetting the oldest");
+            self.sent_sms.remove(0);
+        }
+        let _ = self.sent_sms.push(SentSms { command, references, sent: Instant::now() });
+    }
+
+    /// Applies the TP-Status reported for message reference `reference`.
+    fn track_delivery(&mut self, reference: u8, status: u8) {
+        let Some(index) = self.sent_sms.iter().position(|sms| sms.references.contains(&reference)) else {
+            debug!("Status report for unknown SMS {}", reference);
+            return;
+        };
+        let status = match status {
+            0x00..0x20 => {
+                let sms = &mut self.sent_sms[index];
+                sms.references.retain(|r| *r != reference);
+                if !sms.references.is_empty() {
+                    return;
+                }
+                DeliveryStatus::Delivered
+            }
+            0x20..0x40 => {
+                info!("SMS {} not delivered yet, status {}", reference, status);
+                return;
+            }
+            _ => DeliveryStatus::Failed(status),
+        };
+        let sms = self.sent_sms.remove(index);
+        self.queue_event(SimEvent::SmsDelivery { command: sms.command, status });
+    }
+
+    /// When the oldest sent SMS without a final report is given up.
+    fn delivery_deadline(&self) -> Option<Instant> {
+        self.sent_sms.first().map(|sms| sms.sent + Duration::from_secs(SMS_DELIVERY_TIMEOUT_SECONDS as u64))
+    }
+
+    fn expire_deliveries(&mut self) {
+        while self.delivery_deadline().is_some_and(|deadline| Instant::now() >= deadline) {
+            let sms = self.sent_sms.remove(0);
+            warn!("No status report for {:?}", sms.command);
+            self.queue_event(SimEvent::SmsDelivery { command: sms.command, status: DeliveryStatus::Unconfirmed });
+        }
+    }
+
+    /// Records a `+CREG` state, returning whether it changed.
+    fn track_registration(&mut self, status: RegistrationStatus) -> bool {
+        if self.registration == Some(status) {
+            return false;
+        }
+        info!("Network registration: {:?}", status);
+        self.registration = Some(status);
+        if status.is_registered() {
+            self.lost_since = None;
+            self.loss_reported = false;
+        } else if self.lost_since.is_none() {
+            self.lost_since = Some(Instant::now());
+        }
+        true
+    }
+
+    /// A rebooted module registers again from scratch.
+    fn reset_registration(&mut self) {
+        self.registration = None;
+        if self.lost_since.is_none() {
+            self.lost_since = Some(Instant::now());
+        }
+    }
+
+    /// When an unreported registration loss turns into `NetworkLost`.
+    fn registration_deadline(&self) -> Option<Instant> {
+        if self.loss_reported || !self.powered {
+            return None;
+        }
+        self.lost_since.map(|since| since + self.registration_timeout)
+    }
+
+    /// Reports a registration loss that lasted too long and fails the
+    /// commands waiting for the network.
+    fn check_registration(&mut self) {
+        if self.registration_deadline().is_none_or(|deadline| Instant::now() < deadline) {
+            return;
+        }
+        error!("SIM800 not registered for {} s", self.registration_timeout.as_secs());
+        self.loss_reported = true;
+        self.queue_event(SimEvent::NetworkLost);
+        while let Some(cmd) = self.deferred.pop_front() {
+            self.fail_command(&cmd, Sim800Error::NotRegistered);
+        }
+    }
+
+    fn fail_command(&mut self, cmd: &Command, err: Sim800Error) {
+        if let Some(event) = cmd.failed(err) {
+            self.queue_event(event);
+        }
+    }
+
+    /// Queues an event for `run`. A full queue makes room by dropping its
+    /// oldest droppable event, so that lifecycle and result events are not
+    /// lost to a burst of receive errors or status reports.
+    fn queue_event(&mut self, event: SimEvent) {
+        if !self.pending_events.is_full() {
+            let _ = self.pending_events.push_back(event);
+            return;
+        }
+        let oldest = self.pending_events.iter().position(SimEvent::droppable);
+        let Some(oldest) = oldest.filter(|_| !event.droppable()) else {
+            warn!("Event queue full, dropping {:?}", event);
+            return;
+        };
+        warn!("Event queue full, dropping {:?}", self.pending_events.iter().nth(oldest));
+        for i in 0..self.pending_events.len() {
+            let queued = self.pending_events.pop_front();
+            if let Some(queued) = queued.filter(|_| i != oldest) {
+                let _ = self.pending_events.push_back(queued);
+            }
+        }
+        let _ = self.pending_events.push_back(event);
+    }
+
+    /// When the module goes to sleep, unless it is off or already asleep.
+    fn sleep_deadline(&self) -> Option<Instant> {
+        if self.power_state != ModemPowerState::Awake {
+            return None;
+        }
+        self.sleep_idle.map(|idle| self.last_activity + idle)
+    }
+
+    fn enter_sleep(&mut self) {
+        info!("SIM800 going to sleep");
+        self.control.set_dtr(true);
+        self.set_power_state(ModemPowerState::Sleeping);
+    }
+
+    /// Pulls DTR low if the module sleeps and gives it time to wake up;
+    /// either way it stays awake for another idle period.
+    async fn wake(&mut self) {
+        if self.power_state == ModemPowerState::Sleeping {
+            self.control.set_dtr(false);
+            Timer::after(Duration::from_millis(SIM800_WAKE_DELAY_MS)).await;
+            self.set_power_state(ModemPowerState::Awake);
+        }
+        self.last_activity = Instant::now();
+    }
+
+    fn set_power_state(&mut self, state: ModemPowerState) {
+        if self.power_state == state {
+            return;
+        }
+        let reported = self.power_state != ModemPowerState::Off && state != ModemPowerState::Off;
+        self.power_state = state;
+        // Off and on are reported by `ModemReady`, `PowerOnFailed` and `ModemRestarted`.
+        if reported {
+            self.queue_event(SimEvent::PowerState(state));
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // High Level Operations
+    // -----------------------------------------------------------------------
+
+    /// Switches the module on with a PWRKEY pulse, waits until it reports
+    /// it is ready for calls and SMS, then configures it and loads the
+    /// phone book.
+    pub async fn power_on(&mut self) -> Result<(), PowerOnStage> {
+        self.boot().await?;
+        if !self.configure().await {
+            // It ignores plain `AT`, so `boot` switches it off and on again.
+            warn!("SIM800 still expects CMUX frames, restarting it");
+            self.boot().await?;
+            self.configure().await;
+        }
+        Ok(())
+    }
+
+    /// Power-on up to the module being ready, before it is configured.
+    async fn boot(&mut self) -> Result<(), PowerOnStage> {
+        info!("Powering on SIM800...");
+        self.powered = true;
+        self.reset_registration();
+        self.control.set_multiplexed(false);
+        self.control.set_dtr(false);
+        self.control.set_ttl(true);
+
+        // A pulse toggles the power state, so a module left running (e.g.
+        // across an MCU reset) is shut down first to get a clean boot.
+        if self.send_cmd_wait_ok("AT", 500).await.is_ok() {
+            info!("SIM800 already running, shutting it down first");
+            if self.power_off().await.is_ok() {
+                Timer::after(Duration::from_secs(SIM800_POWER_OFF_SECONDS as u64)).await;
+            }
+            self.powered = true;
+            self.control.set_ttl(true);
+        }
+        self.discard_input();
+
+        let mut ready = Readiness::default();
+        let mut booted = false;
+        // If the module was on but hung, the first pulse switched it off.
+        for _ in 0..2 {
+            self.pulse_power_key().await;
+            if self.wait_boot(&mut ready).await {
+                booted = true;
+                break;
+            }
+        }
+        if !booted {
+            return Err(PowerOnStage::Boot);
+        }
+        self.set_power_state(ModemPowerState::Awake);
+
+        self.wait_ready(&mut ready).await
+    }
+
+    /// Shuts the module down with `AT+CPOWD=1` and disables the TTL level
+    /// shifter once it reported `NORMAL POWER DOWN`.
+    pub async fn power_off(&mut self) -> Result<(), Sim800Error> {
+        info!("Powering off SIM800...");
+        self.powered = false;
+        self.wake().await;
+        self.send_str("AT+CPOWD=1\r\n").await;
+
+        let res = with_timeout(Duration::from_secs(SIM800_POWER_DOWN_TIMEOUT_SECONDS as u64), async {
+            loop {
+                match self.read_response().await? {
+                    Response::Urc(Urc::NormalPowerDown) => return Ok::<(), Sim800Error>(()),
+                    Response::Final(result) => final_result(result)?,
+                    _ => {}
+                }
+            }
+        }).await;
+        // Whether or not it confirmed, the next start is in plain mode.
+        self.control.set_multiplexed(false);
+        self.set_power_state(ModemPowerState::Off);
+        res??;
+
+        self.control.set_ttl(false);
+        Ok(())
+    }
+
+    /// Shuts the module down, gracefully if it still answers, and runs the
+    /// full `power_on` sequence again.
+    pub async fn restart(&mut self) -> Result<(), PowerOnStage> {
+        warn!("Restarting SIM800...");
+        if self.power_off().await.is_ok() {
+            Timer::after(Duration::from_secs(SIM800_POWER_OFF_SECONDS as u64)).await;
+        }
+        self.power_on().await
+    }
+
+    async fn pulse_power_key(&mut self) {
+        self.control.set_enable(true);
+        Timer::after(Duration::from_millis(SIM800_PWRKEY_PULSE_MS)).await;
+        self.control.set_enable(false);
+    }
+
+    /// Whatever was half received belongs to the previous session.
+    fn discard_input(&mut self) {
+        self.lines.discard();
+    }
+
+    /// Waits for `RDY` after a PWRKEY pulse. A module left at autobaud never
+    /// sends it and one fixed at another rate sends it garbled, so an answer
+    /// to `AT` counts as booted too.
+    async fn wait_boot(&mut self, ready: &mut Readiness) -> bool {
+        let rdy = with_timeout(Duration::from_secs(SIM800_BOOT_TIMEOUT_SECONDS as u64), async {
+            loop {
+                match self.read_response().await? {
+                    Response::Urc(Urc::Rdy) => return Ok::<(), Sim800Error>(()),
+                    other => ready.update(&other),
+                }
+            }
+        }).await;
+
+        if matches!(rdy, Ok(Ok(()))) {
+            return true;
+        }
+        match self.baud_rate {
+            Some(baud) => self.detect_baud_rate(baud).await,
+            None => self.send_cmd_wait_ok("AT", 500).await.is_ok(),
+        }
+    }
+
+    /// Sends `AT` at `baud` and then at each of `SIM800_BAUD_RATES` until
+    /// the module answers, leaving the UART at that rate. An autobauding
+    /// module picks up the first rate; it may need a second `AT` for it.
+    async fn detect_baud_rate(&mut self, baud: u32) -> bool {
+        let others = SIM800_BAUD_RATES.into_iter().filter(|&rate| rate != baud);
+        for rate in core::iter::once(baud).chain(others) {
+            self.control.set_baud_rate(rate);
+            self.discard_input();
+            for _ in 0..2 {
+                if self.send_cmd_wait_ok("AT", SIM800_BAUD_PROBE_MS).await.is_ok() {
+                    info!("SIM800 answers at {} baud", rate);
+                    return true;
+                }
+            }
+        }
+        warn!("SIM800 answers at no known baud rate");
+        self.control.set_baud_rate(baud);
+        false
+    }
+
+    /// Fixes the module at `baud` unless `AT+IPR?` says it already is, so
+    /// that it no longer depends on autobauding or a previous setup.
+    async fn lock_baud_rate(&mut self, baud: u32) {
+        let mut current = None;
+        self.send_cmd_with("AT+IPR?", 1000, |r| {
+            if let Response::Info(Info::Ipr(rate)) = *r {
+                current = Some(rate);
+            }
+        }).await.ok();
+        if current == Some(baud) {
+            return;
+        }
+
+        info!("Fixing SIM800 at {} baud (was {:?})", baud, current);
+        let mut cmd = String::<16>::new();
+        use core::fmt::Write;
+        let _ = write!(cmd, "AT+IPR={}", baud);
+        // The reply still comes at the old rate.
+        if let Err(e) = self.send_cmd_wait_ok(&cmd, 1000).await {
+            warn!("AT+IPR refused: {:?}", e);
+            return;
+        }
+        self.control.set_baud_rate(baud);
+        self.discard_input();
+        if let Err(e) = self.send_cmd_wait_ok("AT&W", 1000).await {
+            warn!("Saving the baud rate failed: {:?}", e);
+        }
+    }
+
+    /// Collects the readiness URCs following `RDY`. When they stop coming
+    /// (e.g. they were sent before an autobaud module saw our first `AT`),
+    /// the missing states are queried instead.
+    async fn wait_ready(&mut self, ready: &mut Readiness) -> Result<(), PowerOnStage> {
+        let deadline = Instant::now() + Duration::from_secs(SIM800_READY_TIMEOUT_SECONDS as u64);
+        let mut pin_entered = false;
+        while let Some(mut stage) = ready.missing() {
+            let now = Instant::now();
+            if let Some(fault) = ready.sim_fault() {
+                stage = PowerOnStage::Sim(fault);
+                if fault == SimFault::PinRequired && !pin_entered {
+                    pin_entered = true;
+                    match self.unlock_sim().await {
+                        Ok(()) => {
+                            ready.sim = None;
+                            continue;
+                        }
+                        Err(fault) => stage = PowerOnStage::Sim(fault),
+                    }
+                }
+            } else if now < deadline {
+                let quiet_until = deadline.min(now + Duration::from_secs(SIM800_READY_POLL_SECONDS as u64));
+                let res = with_deadline(quiet_until, async {
+                    while ready.missing().is_some() && ready.sim_fault().is_none() {
+                        let response = self.read_response().await?;
+                        ready.update(&response);
+                    }
+                    Ok::<(), Sim800Error>(())
+                }).await;
+
+                match res {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(_)) => {}
+                    Err(_) => {
+                        self.query_readiness(ready).await;
+                        continue;
+                    }
+                }
+            }
+            warn!("SIM800 not ready: {:?}", stage);
+            return Err(stage);
+        }
+        Ok(())
+    }
+
+    /// Enters the configured PIN, at most once per reset and never with the
+    /// last attempt, so a wrong PIN cannot block the SIM.
+    async fn unlock_sim(&mut self) -> Result<(), SimFault> {
+        let Some(pin) = self.sim_pin else {
+            return Err(SimFault::PinRequired);
+        };
+        if self.pin_rejected {
+            return Err(SimFault::PinRejected);
+        }
+
+        let mut attempts = 0;
+        self.send_cmd_with("AT+SPIC", 1000, |r| {
+            if let Response::Info(Info::Spic { pin1, .. }) = *r {
+                attempts = pin1;
+            }
+        }).await.ok();
+        if attempts <= 1 {
+            warn!("SIM PIN has {} attempts left, not entering it", attempts);
+            return Err(SimFault::LastAttempt);
+        }
+
+        let mut cmd = String::<32>::new();
+        use core::fmt::Write;
+        let _ = write!(cmd, "AT+CPIN=\"{}\"", pin);
+        if let Err(e) = self.send_cmd_wait_ok(&cmd, 5000).await {
+            error!("SIM PIN refused: {:?}", e);
+            self.pin_rejected = true;
+            return Err(SimFault::PinRejected);
+        }
+        Ok(())
+    }
+
+    async fn query_readiness(&mut self, ready: &mut Readiness) {
+        if !ready.functional {
+            self.send_cmd_with("AT+CFUN?", 1000, |r| ready.update(r)).await.ok();
+        }
+        if ready.sim != Some(SimStatus::Ready) {
+            self.send_cmd_with("AT+CPIN?", 1000, |r| ready.update(r)).await.ok();
+        }
+        if !ready.call {
+            self.send_cmd_with("AT+CCALR?", 1000, |r| ready.update(r)).await.ok();
+        }
+        // There is no query for SMS readiness; SMS commands fail until then.
+        if !ready.sms && self.send_cmd_wait_ok("AT+CPMS?", 1000).await.is_ok() {
+            ready.sms = true;
+        }
+    }
+
+    /// Applies the settings the driver relies on and loads the phone book.
+    /// Returns false, having done nothing else, if the module was left in
+    /// CMUX mode without its channels and has to be restarted.
+    async fn configure(&mut self) -> bool {
+        if let Some(baud) = self.baud_rate {
+            self.lock_baud_rate(baud).await;
+        }
+        if self.cmux && !self.enter_cmux().await {
+            return false;
+        }
+        let cmgf = if self.pdu_mode { "AT+CMGF=0" } else { "AT+CMGF=1" };
+        let cmds = [
+            "AT", "ATE0", "AT+CMEE=1", "AT+CLIP=1", cmgf,
+            "AT+CSCS=\"GSM\"", "AT+CNMI=1,2,0,1,0", "AT+CSMP=49,167,0,0",
+            "AT+CREG=1", "AT+DDET=1",
+        ];
+
+        for cmd in cmds {
+            // PDUs carry their own coding; the character set is for text mode.
+            if self.pdu_mode && cmd.starts_with("AT+CSCS") {
+                continue;
+            }
+            let mut attempts = 0;
+            loop {
+                if self.send_cmd_wait_ok(cmd, 1000).await.is_ok() {
+                    break;
+                }
+                attempts += 1;
+                if attempts > 3 {
+                    error!("CMD failed: {}", cmd);
+                    break;
+                }
+                Timer::after(Duration::from_millis(500)).await;
+            }
+        }
+
+        // `AT+CREG=1` only reports changes; the reply updates the registration state.
+        self.send_cmd_wait_ok("AT+CREG?", 1000).await.ok();
+        self.read_identity().await;
+        self.verify_sim();
+        self.load_phone_book().await;
+        self.sweep_stored_sms().await;
+        if self.sleep_idle.is_some() {
+            self.send_cmd_wait_ok("AT+CSCLK=1", 1000).await.ok();
+        }
+        info!("SIM800 Initialized");
+        true
+    }
+
+    /// Switches to multiplexed framing; the settings applied afterwards
+    /// belong to the AT channel. If the channels do not open, the link
+    /// stays plain and CMUX is not tried again. Returns false if the module
+    /// then does not answer on the plain link either.
+    async fn enter_cmux(&mut self) -> bool {
+        if let Err(e) = self.send_cmd_wait_ok("AT+CMUX=0", 1000).await {
+            warn!("CMUX refused, staying on the plain link: {:?}", e);
+            return true;
+        }
+        self.discard_input();
+        self.control.set_multiplexed(true);
+        // Held back until the channels are open, or sent plain if they were not.
+        let answered = self.send_cmd_wait_ok("AT", 1000).await.is_ok();
+        if self.control.is_multiplexed() {
+            return true;
+        }
+        error!("CMUX channels not opened, continuing without CMUX");
+        self.cmux = false;
+        answered
+    }
+
+    async fn read_identity(&mut self) {
+        for cmd in ["AT+GSN", "AT+CCID", "AT+CIMI", "AT+GMR"] {
+            let text = self.query_text(cmd).await;
+            let identity = &mut self.identity;
+            match cmd {
+                "AT+GSN" => identity.imei = truncated(&text),
+                "AT+CCID" => identity.iccid = truncated(text.trim_end_matches(['F', 'f'])),
+                "AT+CIMI" => identity.imsi = truncated(&text),
+                _ => identity.revision = truncated(text.strip_prefix("Revision:").unwrap_or(&text)),
+            }
+        }
+        info!("SIM800 identity: {:?}", self.identity);
+    }
+
+    /// Compares the ICCID with the provisioned one to detect a swapped SIM.
+    fn verify_sim(&mut self) {
+        let found = self.identity.iccid.clone();
+        if found.is_empty() {
+            warn!("ICCID not read, SIM change check skipped");
+            return;
+        }
+        match &self.provisioned_iccid {
+            None => {
+                info!("Provisioning SIM {}", found);
+                self.provisioned_iccid = Some(found.clone());
+                self.queue_event(SimEvent::SimProvisioned(found));
+            }
+            Some(expected) if *expected != found => {
+                error!("SIM changed: expected {}, found {}", expected, found);
+                let expected = expected.clone();
+                self.queue_event(SimEvent::SimChanged { expected, found });
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// First text line answering `cmd`, e.g. the IMEI for `AT+GSN`.
+    async fn query_text(&mut self, cmd: &str) -> String<32> {
+        let mut text = String::new();
+        let res = self.send_cmd_with(cmd, 1000, |r| {
+            if let Response::Text(line) = *r
+                && text.is_empty()
+                && !line.starts_with("AT") {
+                text = truncated(line);
+            }
+        }).await;
+        if let Err(e) = res {
+            warn!("{} failed: {:?}", cmd, e);
+        }
+        text
+    }
+
+    /// Passes on the SMS left on the SIM, received while the module was off
+    /// or stored when routing them failed, and deletes them. Unread ones go
+    /// through the same handling as `+CMT`; the others were seen before or
+    /// are drafts. What does not fit into the event queue, keeping a slot
+    /// for the events that follow, stays unread for the next sweep, and so
+    /// does what could not be read or decoded. A full storage is cleared of everything but unread
+    /// messages.
+    async fn sweep_stored_sms(&mut self) {
+        self.stored_sms = false;
+        let mut storage = None;
+        self.send_cmd_with("AT+CPMS?", 1000, |response| {
+            if let Response::Info(Info::Cpms { used, total }) = *response {
+                storage = Some((used, total));
+            }
+        }).await.ok();
+        let Some((used, total)) = storage.filter(|(used, _)| *used > 0) else {
+            return;
+        };
+        info!("{} of {} SMS slots on the SIM used", used, total);
+
+        // Mode 1 leaves the status of unread messages alone.
+        self.send_str(if self.pdu_mode { "AT+CMGL=4,1" } else { "AT+CMGL=\"ALL\",1" }).await;
+        self.send_str("\r\n").await;
+        let mut handled = Vec::<u8, SMS_SWEEP_BATCH>::new();
+        let res = with_timeout(Duration::from_secs(SIM800_SMS_LIST_TIMEOUT_SECONDS as u64), async {
+            loop {
+                let (index, unread, sender) = match self.read_response().await? {
+                    Response::Final(result) => return final_result(result),
+                    Response::Info(Info::Cmgl { index, unread, sender }) => (index, unread, truncated(sender)),
+                    _ => continue,
+                };
+                // One slot stays free for what follows, e.g. `ModemReady` at power-on.
+                let queue_full = self.pending_events.len() + 1 >= MAXIMUM_PENDING_EVENTS;
+                if handled.is_full() || (unread && queue_full) {
+                    self.read_line().await?;
+                    self.stored_sms = true;
+                    continue;
+                }
+                let taken = if unread {
+                    self.defer_event(SimEvent::SmsReceived { number: sender, message: String::new() }).await
+                } else {
+                    self.read_line().await?;
+                    true
+                };
+                if taken {
+                    let _ = handled.push(index);
+                }
+            }
+        }).await.unwrap_or(Err(Sim800Error::Timeout));
+        if let Err(e) = res {
+            warn!("Listing stored SMS failed: {:?}", e);
+        }
+
+        use core::fmt::Write;
+        for index in &handled {
+            let mut cmd = String::<16>::new();
+            let _ = write!(cmd, "AT+CMGD={}", index);
+            if let Err(e) = self.send_cmd_wait_ok(&cmd, 5000).await {
+                warn!("Deleting stored SMS {} failed: {:?}", index, e);
+            }
+        }
+        if used >= total {
+            warn!("SMS storage full, deleting all but unread messages");
+            if let Err(e) = self.send_cmd_wait_ok("AT+CMGD=1,3", 25000).await {
+                warn!("Clearing the SMS storage failed: {:?}", e);
+            }
+        }
+        // Nothing could be taken this time; wait for the next `+CMTI` or power-on.
+        if handled.is_empty() {
+            self.stored_sms = false;
+        }
+    }
+
+    async fn load_phone_book(&mut self) {
+        self.phone_book.clear();
+        for i in 1..=8 {
+            let mut buf = String::<16>::new();
+            use core::fmt::Write;
+            let _ = write!(buf, "AT+CPBR={}", i);
+
+            let mut entry: Option<String<MAX_PHONE_LENGTH>> = None;
+            let _ = self.send_cmd_with(&buf, 2000, |r| {
+                if let Response::Info(Info::Cpbr { number, .. }) = *r {
+                    entry = String::try_from(number).ok();
+                }
+            }).await;
+            if let Some(number) = entry {
+                let _ = self.phone_book.add_number(&number);
+            }
+        }
+    }
+
+    fn report_power_on(&mut self, res: Result<(), PowerOnStage>) {
+        match res {
+            Ok(()) => self.queue_event(SimEvent::ModemReady),
+            Err(PowerOnStage::Sim(fault)) => {
+                error!("SIM not usable: {:?}", fault);
+                self.queue_event(SimEvent::SimFault(fault));
+            }
+            Err(stage) => {
+                error!("SIM800 power on failed at {:?}", stage);
+                self.queue_event(SimEvent::PowerOnFailed(stage));
+            }
+        }
+    }
+
+    /// The module rebooted on its own (e.g. a brown-out) and lost its settings.
+    async fn recover_from_reboot(&mut self) {
+        warn!("SIM800 rebooted unexpectedly");
+        self.queue_event(SimEvent::ModemRestarted(RestartReason::Rebooted));
+        self.reset_registration();
+        self.control.set_multiplexed(false);
+        let mut ready = Readiness::default();
+        let mut res = self.wait_ready(&mut ready).await;
+        if res.is_ok() && !self.configure().await {
+            res = self.power_on().await;
+        }
+        self.report_power_on(res);
+    }
+
+    /// Pings the module with `AT+CSQ`, recording the signal quality and
+    /// polling the supply voltage, and restarts it after
+    /// `SIM800_MAX_PING_FAILURES` failed pings in a row.
+    async fn supervise(&mut self) {
+        if !self.powered {
+            return;
+        }
+        let mut quality = None;
+        let res = self.send_cmd_with("AT+CSQ", 1000, |r| {
+            if let Response::Info(Info::Csq(q)) = *r {
+                quality = Some(q);
+            }
+        }).await;
+        let err = match res {
+            Ok(()) => {
+                self.ping_failures = 0;
+                if let Some(quality) = quality {
+                    self.record_signal(quality);
+                }
+                self.poll_supply().await;
+                return;
+            }
+            Err(e) => e,
+        };
+        self.ping_failures += 1;
+        warn!("SIM800 ping failed ({}/{}): {:?}", self.ping_failures, SIM800_MAX_PING_FAILURES, err);
+        if self.ping_failures < SIM800_MAX_PING_FAILURES {
+            return;
+        }
+        self.ping_failures = 0;
+        self.queue_event(SimEvent::ModemRestarted(RestartReason::Unresponsive(err)));
+        let res = self.restart().await;
+        self.report_power_on(res);
+    }
+
+    fn record_signal(&mut self, quality: SignalQuality) {
+        if let Some(dbm) = quality.dbm() {
+            self.signal.record(dbm);
+        }
+        self.queue_event(SimEvent::SignalQuality(SignalReport { quality, stats: self.signal }));
+    }
+
+    async fn poll_supply(&mut self) {
+        let mut voltage = None;
+        let res = self.send_cmd_with("AT+CBC", 1000, |r| {
+            if let Response::Info(Info::Cbc { millivolts, .. }) = *r {
+                voltage = Some(millivolts);
+            }
+        }).await;
+        match (res, voltage) {
+            (Ok(()), Some(millivolts)) => self.queue_event(SimEvent::SupplyVoltage(millivolts)),
+            (res, _) => warn!("Supply voltage not reported: {:?}", res),
+        }
+    }
+
+    pub async fn send_sms(&mut self, number: &str, message: &str) -> Result<(), Sim800Error> {
+        self.sent_references.clear();
+        if self.pdu_mode {
+            return self.send_sms_pdu(number, message).await;
+        }
+        self.send_str("AT+CMGS=\"").await;
+        self.send_str(number).await;
+        // Only CR: in text entry mode a trailing LF would become part of the message.
+        self.send_str("\"\r").await;
+        self.wait_prompt().await?;
+
+        self.send_str(message).await;
+        self.submit().await
+    }
+
+    /// `send_sms` in PDU mode; the text goes out in the GSM alphabet or UCS2
+    /// and requests a status report, as `AT+CSMP=49` does in text mode. A
+    /// text too long for one SMS is sent as a concatenated one.
+    async fn send_sms_pdu(&mut self, number: &str, message: &str) -> Result<(), Sim800Error> {
+        let total = sms_pdu::split(message).count();
+        if total == 1 {
+            return self.send_pdu(number, message, None).await;
+        }
+        self.concat_reference = self.concat_reference.wrapping_add(1);
+        for (i, part) in sms_pdu::split(message).enumerate() {
+            let concat = Concat { reference: self.concat_reference.into(), total: total as u8, seq: i as u8 + 1 };
+            self.send_pdu(number, part, Some(concat)).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_pdu(&mut self, number: &str, text: &str, concat: Option<Concat>) -> Result<(), Sim800Error> {
+        let mut pdu = [0u8; SMS_PDU_MAX_OCTETS];
+        let len = sms_pdu::encode_submit(number, text, true, concat, &mut pdu).map_err(Sim800Error::Pdu)?;
+
+        let mut cmd = String::<16>::new();
+        use core::fmt::Write;
+        let _ = write!(cmd, "AT+CMGS={}\r", len);
+        self.send_str(&cmd).await;
+        self.wait_prompt().await?;
+
+        // An empty service centre address selects the one stored on the SIM.
+        self.send_str("00").await;
+        for chunk in pdu[..len].chunks(16) {
+            let mut hex = String::<32>::new();
+            for b in chunk {
+                let _ = write!(hex, "{:02X}", b);
+            }
+            self.send_str(&hex).await;
+        }
+        self.submit().await
+    }
+
+    /// Ends the SMS with Ctrl-Z and records the message reference the
+    /// network assigned to it.
+    async fn submit(&mut self) -> Result<(), Sim800Error> {
+        let ctrl_z = [0x1Au8];
+        let _ = self.tx.write_all(&ctrl_z).await;
+
+        let mut reference = None;
+        self.send_cmd_with("", 10000, |response| {
+            if let Response::Info(Info::Cmgs { reference: mr }) = *response {
+                reference = Some(mr);
+            }
+        }).await?;
+        if let Some(reference) = reference {
+            let _ = self.sent_references.push(reference);
+        }
+        Ok(())
+    }
+
+    /// Waits for the `>` prompt after `AT+CMGS`.
+    async fn wait_prompt(&mut self) -> Result<(), Sim800Error> {
+        with_timeout(Duration::from_secs(5), async {
+            loop {
+                match self.read_response_frame(true).await? {
+                    Response::Prompt => return Ok(()),
+                    // An error code says more than the missing prompt.
+                    Response::Final(result) => return final_result(result).and(Err(Sim800Error::NoPrompt)),
+                    _ => {}
+                }
+            }
+        }).await.map_err(|_| Sim800Error::NoPrompt)?
+    }
+
+    pub async fn make_call_dtmf(&mut self, number: &str, dtmf: &str) -> Result<(), Sim800Error> {
+        self.send_str("ATD").await;
+        self.send_str(number).await;
+        self.send_str(";\r\n").await;
+
+        // The dial step ends with OK, or right away with BUSY, NO CARRIER
+        // etc.; no answer yet just means the call is still being set up.
+        match self.send_cmd_wait_ok("", 5000).await {
+            Ok(()) | Err(Sim800Error::Timeout) => {}
+            Err(e) => {
+                self.send_cmd_wait_ok("AT+CHUP", 1000).await.ok();
+                return Err(e);
+            }
+        }
+
+        let result = with_timeout(Duration::from_secs(20), async {
+             loop {
+                match self.read_response().await? {
+                    Response::Urc(Urc::Dtmf(key)) if ONLINE_SIGNAL.starts_with(key) => return Ok(()),
+                    Response::Final(FinalResult::Ok) => {}
+                    Response::Final(result) => return final_result(result),
+                    _ => {}
+                }
+             }
+        }).await;
+
+        if let Err(e) = result.map_err(Sim800Error::from).and_then(|r| r) {
+            self.send_cmd_wait_ok("AT+CHUP", 1000).await.ok();
+            return Err(e);
+        }
+
+        let mut out_buf = [0u8; 64];
+        if let Some(csv) = separate_chars_by_commas(dtmf, &mut out_buf) {
+             let mut cmd = String::<64>::new();
+             use core::fmt::Write;
+             let _ = write!(cmd, "AT+VTS=\"{}\"", csv);
+             self.send_cmd_wait_ok(&cmd, 5000).await?;
+        }
+
+        let confirm_res = with_timeout(Duration::from_secs(5), async {
+             loop {
+                if let Response::Urc(Urc::Dtmf(key)) = self.read_response().await?
+                    && CONFIRMATION_SIGNAL.starts_with(key) {
+                    return Ok(());
+                }
+             }
+        }).await;
+
+        self.send_cmd_wait_ok("AT+CHUP", 1000).await.ok();
+        
+        match confirm_res {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(Sim800Error::Unconfirmed),
+        }
+    }
+
+    pub async fn handle_incoming_call_flow<M: RawMutex, const N: usize>(&mut self, event_channel: &Sender<'_, M, SimEvent, N>) {
+        self.send_cmd_wait_ok("ATA", 2000).await.ok();
+        Timer::after(Duration::from_secs(1)).await;
+        
+        let mut cmd = String::<32>::new();
+        use core::fmt::Write;
+        let _ = write!(cmd, "AT+VTS=\"{}\"", ONLINE_SIGNAL);
+        self.send_cmd_wait_ok(&cmd, 2000).await.ok();
+
+        let mut dtmf_buf = String::<DTMF_PACKET_LENGTH>::new();
+        
+        let _ = with_timeout(Duration::from_secs(10), async {
+            loop {
+                match self.read_response().await? {
+                    Response::Urc(Urc::Dtmf(c)) => {
+                        dtmf_buf.push(c).ok();
+                        event_channel.send(SimEvent::DtmfReceived(c)).await;
+                    }
+                    Response::Final(FinalResult::NoCarrier) => return Err(Sim800Error::NoCarrier),
+                    _ => {}
+                }
+                if dtmf_buf.len() >= DTMF_PACKET_LENGTH { return Ok(()); }
+            }
+        }).await;
+
+        cmd.clear();
+        let _ = write!(cmd, "AT+VTS=\"{}\"", CONFIRMATION_SIGNAL);
+        self.send_cmd_wait_ok(&cmd, 2000).await.ok();
+        
+        Timer::after(Duration::from_secs(1)).await;
+        self.send_cmd_wait_ok("AT+CHUP", 1000).await.ok();
+        
+        event_channel.send(SimEvent::CallEnded).await;
+    }
+
+    async fn execute<M: RawMutex, const N: usize>(&mut self,
+        cmd: Command,
+        event_channel: &Sender<'_, M, SimEvent, N>,
+        uptime_sec: u64,
+    ) {
+        info!("Processing command: {:?}", cmd);
+        match cmd {
+            Command::Init => {
+                let res = self.power_on().await;
+                self.report_power_on(res);
+            },
+            Command::PowerOff => {
+                if let Err(e) = self.power_off().await {
+                    warn!("SIM800 did not confirm power down: {:?}", e);
+                }
+            },
+            Command::SendMessage { phone_number, message } => {
+                let res = self.send_sms(&phone_number, &message).await;
+                if res.is_ok() {
+                    self.track_sms(Command::SendMessage { phone_number, message });
+                }
+                event_channel.send(SimEvent::SmsSent(res)).await;
+            },
+            Command::SendAlarmSms { message, resends } => {
+                 let mut target_num = String::<MAX_PHONE_LENGTH>::new();
+                 let mut found = false;
+                 if let Some(num) = self.phone_book.get_first() {
+                     target_num.push_str(num).ok();
+                     found = true;
+                 }
+                 let res = if found {
+                     self.send_sms(&target_num, &message).await
+                 } else {
+                     warn!("No phone number for alarm SMS!");
+                     Err(Sim800Error::NoRecipient)
+                 };
+                 if res.is_ok() {
+                     self.track_sms(Command::SendAlarmSms { message, resends });
+                 }
+                 event_channel.send(SimEvent::SmsSent(res)).await;
+            },
+            Command::CallAlarmWithDtmf { dtmf } => {
+                 let mut target_num = String::<MAX_PHONE_LENGTH>::new();
+                 let mut found = false;
+                 if let Some(num) = self.phone_book.get_first() {
+                     target_num.push_str(num).ok();
+                     found = true;
+                 }
+
+                 if found {
+                     let is_duplicate = (dtmf == self.last_alarm_dtmf) && 
+                                        (uptime_sec.saturating_sub(self.last_alarm_time) < 120); 
+
+                     if is_duplicate {
+                         warn!("Skipping duplicate alarm call for DTMF {} (Last: {}s ago)", dtmf, uptime_sec - self.last_alarm_time);
+                         event_channel.send(SimEvent::CallExecuted(Ok(()))).await;
+                     } else {
+                         info!("Calling Alarm: {} with DTMF: {}", target_num, dtmf);
+                         match self.make_call_dtmf(&target_num, &dtmf).await {
+                             Ok(_) => {
+                                 info!("Alarm confirmed (#).");
+                                 self.last_alarm_dtmf = dtmf.clone();
+                                 self.last_alarm_time = uptime_sec;
+                                 event_channel.send(SimEvent::CallExecuted(Ok(()))).await;
+                             },
+                             Err(e) => {
+                                 warn!("Alarm call failed/unconfirmed: {:?}", e);
+                                 event_channel.send(SimEvent::CallExecuted(Err(e))).await;
+                             }
+                         }
+                     }
+                 } else {
+                     warn!("No phone number for alarm call!");
+                     event_channel.send(SimEvent::CallExecuted(Err(Sim800Error::NoRecipient))).await;
+                 }
+            },
+            Command::CallWithDtmf { phone_number, dtmf } => {
+                let _ = self.make_call_dtmf(&phone_number, &dtmf).await;
+            },
+            Command::HandleIncomingCall { .. } => {
+                self.handle_incoming_call_flow(event_channel).await;
+            },
+            Command::QueryIdentity => {
+                event_channel.send(SimEvent::Identity(self.identity.clone())).await;
+            },
+            Command::Ussd { code } => {
+                let res = self.send_ussd(&code).await;
+                event_channel.send(SimEvent::UssdResponse(res)).await;
+            },
+            Command::UpdateTime => {
+                match self.execute_update_time().await {
+                    Ok(time) => {
+                        info!("Time updated (CMD): {}-{}-{} {}:{}:{}", 
+                            time.year, time.month, time.day, time.hour, time.minute, time.second);
+                        event_channel.send(SimEvent::TimeReceived(time)).await;
+                    }
+                    Err(e) => warn!("Failed to read time from +CCLK: {:?}", e),
+                }
+            },
+        }
+    }
+
+    pub async fn run<M: RawMutex, const N: usize>(&mut self,
+        cmd_channel: Receiver<'_, M, Command, N>,
+        event_channel: Sender<'_, M, SimEvent, N>
+    ) {
+        let res = self.power_on().await;
+        self.report_power_on(res);
+        
+        let mut uptime_sec: u64 = 0;
+        let mut next_ping = Instant::now() + self.ping_period;
+        
+        loop {
+            use embassy_futures::select::{select4, Either4};
+
+            while let Some(event) = self.pending_events.pop_front() {
+                event_channel.send(event).await;
+            }
+
+            if self.stored_sms && self.powered {
+                self.sweep_stored_sms().await;
+                continue;
+            }
+            
+            if self.is_registered() && let Some(cmd) = self.deferred.pop_front() {
+                self.execute(cmd, &event_channel, uptime_sec).await;
+                continue;
+            }
+
+            let wake = [
+                self.registration_deadline(),
+                self.sleep_deadline(),
+                self.assembler.deadline(),
+                self.delivery_deadline(),
+            ]
+                .into_iter()
+                .flatten()
+                .fold(next_ping, Instant::min);
+            let selection = select4(
+                self.lines.read(false),
+                cmd_channel.receive(),
+                Timer::at(wake),
+                self.control.wake_requested(),
+            ).await;
+            uptime_sec += 1; 
+
+            match selection {
+                Either4::First(line_res) => {
+                    let mut event = None;
+                    let mut report = None;
+                    let mut rebooted = false;
+
+                    let line = match line_res {
+                        Ok(()) => self.lines.line(),
+                        Err(Sim800Error::Closed) => "",
+                        Err(e) => {
+                            self.queue_event(SimEvent::ReceiveError(e));
+                            ""
+                        }
+                    };
+                    if !line.trim().is_empty() {
+                        info!("RX: {}", line);
+                        
+                        let response = parse_line(line);
+                        report = StatusUrc::from_response(&response);
+                        event = Self::urc_event(&response);
+                        match response {
+                            Response::Urc(Urc::Dtmf(c)) => {
+                                event = Some(SimEvent::DtmfReceived(c));
+                            }
+                            Response::Info(Info::Cclk(time)) => {
+                                info!("Time received (URC): {}-{}-{} {}:{}:{}", 
+                                    time.year, time.month, time.day, time.hour, time.minute, time.second);
+                                event = Some(SimEvent::TimeReceived(time));
+                            }
+                            Response::Urc(Urc::Rdy) => rebooted = self.powered,
+                            Response::Urc(Urc::Cmti { index }) => {
+                                info!("SMS {} stored on the SIM", index);
+                                self.stored_sms = true;
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    if let Some(report) = report {
+                        self.receive_status_report(report).await;
+                    }
+                    // Goes through the same queue as URCs seen during commands to keep their order.
+                    if let Some(event) = event {
+                        self.defer_event(event).await;
+                        // The URC woke the module; keep it awake to act on it.
+                        self.wake().await;
+                    }
+                    if rebooted {
+                        self.recover_from_reboot().await;
+                    }
+                },
+                Either4::Second(cmd) => {
+                    if !cmd.needs_network() || self.is_registered() {
+                        self.execute(cmd, &event_channel, uptime_sec).await;
+                    } else if self.loss_reported {
+                        self.fail_command(&cmd, Sim800Error::NotRegistered);
+                    } else {
+                        info!("Not registered, deferring {:?}", cmd);
+                        if let Err(cmd) = self.deferred.push_back(cmd) {
+                            self.fail_command(&cmd, Sim800Error::NotRegistered);
+                        }
+                    }
+                }
+                Either4::Third(()) => {
+                    if Instant::now() >= next_ping {
+                        self.supervise().await;
+                        next_ping = Instant::now() + self.ping_period;
+                    }
+                    self.check_registration();
+                    if self.sleep_deadline().is_some_and(|deadline| Instant::now() >= deadline) {
+                        if self.control.keep_awake() {
+                            self.last_activity = Instant::now();
+                        } else {
+                            self.enter_sleep();
+                        }
+                    }
+                    while let Some(sms) = self.assembler.expire(Instant::now()) {
+                        warn!("Concatenated SMS from {} incomplete, passing on what arrived", sms.sender.as_str());
+                        self.queue_event(SimEvent::SmsReceived { number: sms.sender, message: sms.text });
+                    }
+                    self.expire_deliveries();
+                }
+                Either4::Fourth(()) => self.wake().await,
+            }
+        }
+    }
+}
+
+impl<T: Write, R: Read, P: PowerControl> AtChannel for Sim800Driver<T, R, P> {
+    async fn send_str(&mut self, s: &str) {
+        self.wake().await;
+        if s.is_empty() { return; }
+        let _ = self.tx.write_all(s.as_bytes()).await;
+    }
+
+    /// Incoming SMS and calls can arrive while a command is in flight; they
+    /// are queued and forwarded to the event channel by `run` instead of
+    /// being lost.
+    async fn read_response(&mut self) -> Result<Response<'_>, Sim800Error> {
+        self.read_response_frame(false).await
+    }
+}
+
+/// Queries a `QueryChannel` answers.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Query {
+    UpdateTime,
+    Ussd {
+        code: String<USSD_CODE_LENGTH>,
+    },
+}
+
+impl From<Query> for Command {
+    fn from(query: Query) -> Self {
+        match query {
+            Query::UpdateTime => Command::UpdateTime,
+            Query::Ussd { code } => Command::Ussd { code },
+        }
+    }
+}
+
+/// Second AT consumer answering time and USSD queries on a channel of its
+/// own (`CMUX_AUX_DLCI`), so they do not wait while the driver is busy with
+/// a call. It owns no control lines: the module is woken and kept awake
+/// through `ModemWake`, and unsolicited results are left to the driver, on
+/// whose channel the module reports them.
+pub struct QueryChannel<'a, T, R, M: RawMutex> {
+    tx: T,
+    lines: LineReader<R>,
+    wake: &'a ModemWake<M>,
+}
+
+impl<'a, T: Write, R: Read, M: RawMutex> QueryChannel<'a, T, R, M> {
+    pub fn new(tx: T, rx: R, wake: &'a ModemWake<M>) -> Self {
+        Self { tx, lines: LineReader::new(rx), wake }
+    }
+
+    /// Answers `query` with the event the driver would send for it; `None`
+    /// if the time could not be read.
+    pub async fn answer(&mut self, query: Query) -> Option<SimEvent> {
+        let _hold = self.wake.hold().await;
+        // Channel settings are lost whenever the module restarts.
+        self.send_cmd_wait_ok("ATE0;+CMEE=1", 1000).await.ok();
+        match query {
+            Query::UpdateTime => match self.execute_update_time().await {
+                Ok(time) => Some(SimEvent::TimeReceived(time)),
+                Err(e) => {
+                    warn!("Failed to read time from +CCLK: {:?}", e);
+                    None
+                }
+            },
+            Query::Ussd { code } => Some(SimEvent::UssdResponse(self.send_ussd(&code).await)),
+        }
+    }
+
+    /// Answers `queries`, skipping whatever the module sends in between
+    /// (e.g. a USSD answer that came too late).
+    pub async fn run<MC: RawMutex, const Q: usize, const N: usize>(&mut self,
+        queries: Receiver<'_, MC, Query, Q>,
+        event_channel: Sender<'_, MC, SimEvent, N>
+    ) {
+        use embassy_futures::select::{select, Either};
+
+        loop {
+            match select(self.lines.read(false), queries.receive()).await {
+                Either::First(Ok(())) => debug!("RX (queries): {}", self.lines.line()),
+                Either::First(Err(_)) => {}
+                Either::Second(query) => {
+                    if let Some(event) = self.answer(query).await {
+                        event_channel.send(event).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T: Write, R: Read, M: RawMutex> AtChannel for QueryChannel<'_, T, R, M> {
+    async fn send_str(&mut self, s: &str) {
+        let _ = self.tx.write_all(s.as_bytes()).await;
+    }
+
+    async fn read_response(&mut self) -> Result<Response<'_>, Sim800Error> {
+        loop {
+            match self.lines.read(false).await {
+                Ok(()) => return Ok(parse_line(self.lines.line())),
+                Err(Sim800Error::Closed) => return Err(Sim800Error::Closed),
+                Err(_) => {}
+            }
+        }
+    }
+}
+/// Text of a `+CUSD` answer, decoded if UCS2 coded (data coding scheme
+/// `0x11` or `01xx10xx`).
+fn ussd_text(text: Option<&str>, dcs: Option<u8>) -> Result<String<SIM800_LINE_BUFFER_SIZE>, Sim800Error> {
+    let text = text.ok_or(Sim800Error::UssdRejected)?;
+    let ucs2 = dcs.is_some_and(|dcs| dcs == 0x11 || dcs & 0xCC == 0x48);
+    Ok(ucs2.then(|| decode_ucs2_hex(text)).flatten().unwrap_or_else(|| truncated(text)))
+}
+
+/// Copies as much of `s` as fits into a fixed capacity string.
+fn truncated<const N: usize>(s: &str) -> String<N> {
+    let mut out = String::new();
+    for c in s.chars() {
+        if out.push(c).is_err() {
+            break;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use heapless::Vec;
+
+    #[derive(Default)]
+    struct Recorder(Vec<u8, 128>);
+
+    impl embedded_io_async::ErrorType for Recorder {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Write for Recorder {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.0.extend_from_slice(buf).unwrap();
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    struct NoPower;
+
+    impl PowerControl for NoPower {
+        fn set_enable(&mut self, _high: bool) {}
+        fn set_ttl(&mut self, _high: bool) {}
+    }
+
+    fn driver(rx: &[u8]) -> Sim800Driver<Recorder, &[u8], NoPower> {
+        Sim800Driver::new(Recorder::default(), rx, NoPower)
+    }
+
+    #[test]
+    fn command_is_terminated_and_ok_accepted() {
+        let mut drv = driver(b"\r\nOK\r\n");
+        assert_eq!(block_on(drv.send_cmd_wait_ok("AT", 100)), Ok(()));
+        assert_eq!(drv.tx.0.as_slice(), b"AT\r\n");
+    }
+
+    #[test]
+    fn error_reply_fails_command() {
+        let mut drv = driver(b"ERROR\r\n");
+        assert_eq!(block_on(drv.send_cmd_wait_ok("AT+CPBR=1", 100)), Err(Sim800Error::Error));
+    }
+
+    #[test]
+    fn error_codes_are_returned() {
+        let mut drv = driver(b"\r\n+CME ERROR: 10\r\n");
+        let err = block_on(drv.send_cmd_wait_ok("AT+CPBR=1", 100)).unwrap_err();
+        assert_eq!(err, Sim800Error::Cme(10));
+        assert!(err.is_sim_failure());
+
+        let mut drv = driver(b"\r\n+CMS ERROR: 332\r\n");
+        let err = block_on(drv.send_cmd_wait_ok("AT+CMGS", 100)).unwrap_err();
+        assert_eq!(err, Sim800Error::Cms(332));
+        assert!(err.is_network_failure() && !err.is_sim_failure());
+    }
+
+    #[test]
+    fn closed_stream_fails_command() {
+        let mut drv = driver(b"");
+        assert_eq!(block_on(drv.send_cmd_wait_ok("AT", 100)), Err(Sim800Error::Closed));
+    }
+
+    #[test]
+    fn phone_book_is_filled_from_cpbr() {
+        let mut drv = driver(
+            b"+CPBR: 1,\"+79001234567\",145,\"Admin\"\r\n\r\nOK\r\n\
+              OK\r\n\
+              +CPBR: 3,\"+790012345678901234\",145,\"Too long\"\r\n\r\nOK\r\n\
+              +CPBR: 4,\"+79007654321\",145,\"Guard\"\r\n\r\nOK\r\n",
+        );
+        drv.phone_book.add_number("+70000000000").unwrap();
+        block_on(drv.load_phone_book());
+        assert_eq!(drv.phone_book.get_first(), Some("+79001234567"));
+        assert_eq!(drv.phone_book.get(1), Some("+79007654321"));
+        assert_eq!(drv.phone_book.get(2), None);
+    }
+
+    #[test]
+    fn update_time_reads_cclk() {
+        let mut drv = driver(b"+CCLK: \"26/01/09,23:15:31+12\"\r\n\r\nOK\r\n");
+        let time = block_on(drv.execute_update_time()).unwrap();
+        assert_eq!((time.year, time.month, time.day), (26, 1, 9));
+        assert_eq!(drv.tx.0.as_slice(), b"AT+CCLK?\r\n");
+    }
+
+    #[test]
+    fn urcs_during_command_are_queued() {
+        let mut drv = driver(
+            b"+CMT: \"+79001234567\",\"\",\"26/01/09,23:15:31+12\"\r\n\
+              PPP;101\r\n\
+              +CLIP: \"+79007654321\",145,\"\",0,\"\",0\r\n\
+              OK\r\n",
+        );
+        assert_eq!(block_on(drv.send_cmd_wait_ok("AT+CSQ", 100)), Ok(()));
+
+        assert_eq!(
+            drv.pending_events.pop_front(),
+            Some(SimEvent::SmsReceived { number: truncated("+79001234567"), message: truncated("PPP;101") })
+        );
+        assert_eq!(
+            drv.pending_events.pop_front(),
+            Some(SimEvent::CallReceived { number: truncated("+79007654321") })
+        );
+    }
+
+    #[test]
+    fn pdu_sms_is_decoded() {
+        let mut drv = driver(
+            b"+CMT: ,26\r\n\
+              07919730071111F1040B919700214365F70008621090321554210E041F0420041E0412041504200410\r\n\
+              +CMT: ,3\r\n\
+              07914\r\n\
+              OK\r\n",
+        );
+        drv.set_pdu_mode(true);
+        assert_eq!(block_on(drv.send_cmd_wait_ok("AT+CSQ", 100)), Ok(()));
+
+        assert_eq!(
+            drv.pending_events.pop_front(),
+            Some(SimEvent::SmsReceived { number: truncated("+79001234567"), message: truncated("ПРОВЕРА") })
+        );
+        assert_eq!(drv.pending_events.pop_front(), Some(SimEvent::ReceiveError(Sim800Error::Pdu(PduError::Hex))));
+    }
+
+    #[test]
+    fn concatenated_pdu_sms_is_reassembled() {
+        // Part 2 of 2 ("ДО"), then part 1 ("ПРОВЕРКА "), 8-bit reference 5.
+        let mut drv = driver(
+            b"+CMT: ,30\r\n\
+              07919730071111F1440B919700214365F70008621090321554210A050003050202\
+              0414041E\r\n\
+              +CMT: ,42\r\n\
+              07919730071111F1440B919700214365F700086210903215542118050003050201\
+              041F0420041E041204150420041A04100020\r\n\
+              OK\r\n",
+        );
+        drv.set_pdu_mode(true);
+        assert_eq!(block_on(drv.send_cmd_wait_ok("AT+CSQ", 100)), Ok(()));
+
+        assert_eq!(
+            drv.pending_events.pop_front(),
+            Some(SimEvent::SmsReceived { number: truncated("+79001234567"), message: truncated("ПРОВЕРКА ДО") })
+        );
+        assert!(drv.pending_events.is_empty());
+    }
+
+    #[test]
+    fn status_reports_are_matched_to_sent_parts() {
+        let mut drv = driver(
+            b"+CDS: 25\r\n07919730071111F106170B919700214365F7621090321554216210903215742100\r\n\
+              +CDS: 25\r\n07919730071111F106180B919700214365F7621090321554216210903215742146\r\n\
+              +CDS: 25\r\n07919730071111F106190B919700214365F7621090321554216210903215742100\r\n\
+              OK\r\n",
+        );
+        drv.pdu_mode = true;
+        drv.sent_references.extend_from_slice(&[0x17, 0x18]).unwrap();
+        let command = Command::SendAlarmSms { message: truncated("PPP_123_260109231531"), resends: 0 };
+        drv.track_sms(command.clone());
+        assert!(drv.delivery_deadline().is_some());
+
+        assert_eq!(block_on(drv.send_cmd_wait_ok("AT", 100)), Ok(()));
+        // The first part arrived, the second failed; the third report is not ours.
+        assert_eq!(
+            drv.pending_events.pop_front(),
+            Some(SimEvent::SmsDelivery { command, status: DeliveryStatus::Failed(0x46) })
+        );
+        assert_eq!(drv.pending_events.pop_front(), None);
+        assert_eq!(drv.delivery_deadline(), None);
+    }
+
+    #[test]
+    fn registration_changes_are_reported_once() {
+        let mut drv = driver(b"+CREG: 2\r\n+CREG: 2\r\n+CREG: 1,1\r\n+CREG: 5\r\nOK\r\n");
+        assert_eq!(block_on(drv.send_cmd_wait_ok("AT+CREG?", 100)), Ok(()));
+
+        let reported: heapless::Vec<_, 4> = drv.pending_events.iter().cloned().collect();
+        assert_eq!(
+            reported.as_slice(),
+            [
+                SimEvent::NetworkStatus(RegistrationStatus::Searching),
+                SimEvent::NetworkStatus(RegistrationStatus::Home),
+                SimEvent::NetworkStatus(RegistrationStatus::Roaming),
+            ]
+        );
+        assert!(drv.is_registered());
+        assert_eq!(drv.lost_since, None);
+    }
+
+    #[test]
+    fn ping_records_signal_quality_and_supply_voltage() {
+        let mut drv = driver(
+            b"+CSQ: 20,0\r\nOK\r\n+CBC: 0,75,3950\r\nOK\r\n\
+              +CSQ: 99,99\r\nOK\r\n+CBC: 0,74,3940\r\nOK\r\n",
+        );
+        drv.powered = true;
+        block_on(drv.supervise());
+        block_on(drv.supervise());
+
+        let mut stats = SignalStats::new();
+        stats.record(-73);
+        let reported: heapless::Vec<_, 4> = drv.pending_events.iter().cloned().collect();
+        assert_eq!(
+            reported.as_slice(),
+            [
+                SimEvent::SignalQuality(SignalReport { quality: SignalQuality { rssi: 20, ber: 0 }, stats }),
+                SimEvent::SupplyVoltage(3950),
+                SimEvent::SignalQuality(SignalReport { quality: SignalQuality { rssi: 99, ber: 99 }, stats }),
+                SimEvent::SupplyVoltage(3940),
+            ]
+        );
+    }
+
+    #[test]
+    fn ussd_answer_is_decoded() {
+        let mut drv = driver(b"OK\r\n+CUSD: 0,\"04110430043B0430043D044100200035003000200440002E\",72\r\n");
+        let answer = block_on(drv.send_ussd("*100#")).unwrap();
+        assert_eq!(answer.as_str(), "Баланс 50 р.");
+        assert_eq!(drv.tx.0.as_slice(), b"AT+CUSD=1,\"*100#\"\r\n");
+    }
+
+    #[test]
+    fn ussd_menu_is_closed() {
+        let mut drv = driver(b"+CUSD: 1,\"1 Balance, 2 Tariff\",15\r\nOK\r\nOK\r\n");
+        let answer = block_on(drv.send_ussd("*100#")).unwrap();
+        assert_eq!(answer.as_str(), "1 Balance, 2 Tariff");
+        assert!(drv.tx.0.ends_with(b"AT+CUSD=2\r\n"));
+
+        let mut drv = driver(b"OK\r\n+CUSD: 4\r\n");
+        assert_eq!(block_on(drv.send_ussd("*100#")), Err(Sim800Error::UssdRejected));
+    }
+
+    #[test]
+    fn long_fields_are_truncated() {
+        let s: String<4> = truncated("123456");
+        assert_eq!(s.as_str(), "1234");
+    }
+
+    #[test]
+    fn overlong_line_is_dropped_and_reported() {
+        let mut rx: Vec<u8, 512> = Vec::new();
+        rx.resize(SIM800_MAX_LINE_LENGTH + 20, b'x').unwrap();
+        rx.extend_from_slice(b"OK\r\n\r\nOK\r\n").unwrap();
+        let mut drv = driver(&rx);
+        assert_eq!(block_on(drv.send_cmd_wait_ok("AT", 100)), Ok(()));
+        assert_eq!(drv.pending_events.pop_front(), Some(SimEvent::ReceiveError(Sim800Error::LineOverflow)));
+        assert!(drv.pending_events.is_empty());
+    }
+
+    #[test]
+    fn full_event_queue_drops_status_events_first() {
+        let mut drv = driver(b"");
+        drv.queue_event(SimEvent::ReceiveError(Sim800Error::Utf8));
+        drv.queue_event(SimEvent::SimProvisioned(String::new()));
+        drv.queue_event(SimEvent::NetworkStatus(RegistrationStatus::Home));
+        drv.queue_event(SimEvent::ReceiveError(Sim800Error::LineOverflow));
+        drv.queue_event(SimEvent::ModemReady);
+        drv.queue_event(SimEvent::SupplyVoltage(4000));
+        drv.queue_event(SimEvent::CallEnded);
+
+        let queued: heapless::Vec<_, 4> = drv.pending_events.iter().cloned().collect();
+        assert_eq!(
+            queued,
+            [
+                SimEvent::SimProvisioned(String::new()),
+                SimEvent::ReceiveError(Sim800Error::LineOverflow),
+                SimEvent::ModemReady,
+                SimEvent::CallEnded,
+            ]
+        );
+
+        // Once nothing droppable is left, new events are dropped.
+        drv.queue_event(SimEvent::ModemReady);
+        drv.queue_event(SimEvent::CallEnded);
+        assert_eq!(drv.pending_events.iter().filter(|e| **e == SimEvent::CallEnded).count(), 1);
+        assert!(!drv.pending_events.iter().any(SimEvent::droppable));
+    }
+
+    #[test]
+    fn queued_payloads_stay_small() {
+        // Every slot of the command and event channels holds one; a full
+        // SMS text is all they may grow by.
+        assert!(core::mem::size_of::<Command>() <= 64 + SMS_TEXT_LENGTH);
+        assert!(core::mem::size_of::<SimEvent>() <= 64 + SMS_TEXT_LENGTH);
+    }
+
+    #[test]
+    fn query_channel_answers_like_the_driver() {
+        let wake = ModemWake::<embassy_sync::blocking_mutex::raw::NoopRawMutex>::new();
+        let rx = b"OK\r\n+CCLK: \"26/01/09,23:15:31+12\"\r\n\r\nOK\r\n";
+        let mut channel = QueryChannel::new(Recorder::default(), &rx[..], &wake);
+        let event = block_on(channel.answer(Query::UpdateTime));
+        assert!(matches!(event, Some(SimEvent::TimeReceived(time)) if time.day == 9));
+        assert_eq!(channel.tx.0.as_slice(), b"ATE0;+CMEE=1\r\nAT+CCLK?\r\n");
+        assert!(!wake.is_held());
+    }
+
+    #[test]
+    fn partial_line_survives_cancelled_read() {
+        let mut drv = driver(b"+CSQ: 1");
+        assert_eq!(block_on(drv.read_line()), Err(Sim800Error::Closed));
+        drv.lines.rx = b"5,0\r\n";
+        assert_eq!(block_on(drv.read_line()), Ok("+CSQ: 15,0"));
+    }
+}
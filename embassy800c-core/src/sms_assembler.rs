@@ -0,0 +1,176 @@
+// /embassy800c-core/src/sms_assembler.rs
+//! Reassembly of concatenated SMS. Parts may arrive in any order; a message
+//! still missing parts after `SMS_CONCAT_TIMEOUT_SECONDS` is handed out
+//! with what arrived.
+
+use embassy_time::{Duration, Instant};
+use heapless::String;
+
+use crate::constants::{MAX_PHONE_LENGTH, SMS_CONCAT_SLOTS, SMS_CONCAT_TIMEOUT_SECONDS, SMS_MAX_PARTS, SMS_TEXT_LENGTH};
+use crate::sms_pdu::Concat;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AssembledSms {
+    pub sender: String<MAX_PHONE_LENGTH>,
+    pub text: String<SMS_TEXT_LENGTH>,
+}
+
+struct Pending {
+    sender: String<MAX_PHONE_LENGTH>,
+    reference: u16,
+    total: u8,
+    /// Bit `n` is set once part `n + 1` arrived.
+    received: u32,
+    /// Where the text of each part lies in `text`, which holds the parts in
+    /// the order they arrived. Parts beyond `SMS_MAX_PARTS` are counted only.
+    spans: [Option<(usize, usize)>; SMS_MAX_PARTS],
+    text: String<SMS_TEXT_LENGTH>,
+    started: Instant,
+}
+
+impl Pending {
+    fn is_complete(&self) -> bool {
+        self.received.count_ones() == u32::from(self.total)
+    }
+
+    fn assemble(self) -> AssembledSms {
+        let mut text = String::new();
+        for (start, end) in self.spans.iter().flatten() {
+            let _ = text.push_str(&self.text[*start..*end]);
+        }
+        AssembledSms { sender: self.sender, text }
+    }
+}
+
+pub struct SmsAssembler {
+    slots: [Option<Pending>; SMS_CONCAT_SLOTS],
+}
+
+impl SmsAssembler {
+    pub const fn new() -> Self {
+        Self { slots: [const { None }; SMS_CONCAT_SLOTS] }
+    }
+
+    /// Adds a part, returning the message once all parts are there.
+    pub fn push(&mut self, sender: &str, concat: Concat, text: &str, now: Instant) -> Option<AssembledSms> {
+        // A bit per part; longer messages never complete and time out.
+        if concat.seq > 32 {
+            return None;
+        }
+        let index = self.slot_for(sender, concat, now);
+        let pending = self.slots[index].as_mut()?;
+        let bit = 1u32 << (concat.seq - 1);
+        if pending.received & bit != 0 {
+            return None;
+        }
+        pending.received |= bit;
+
+        if let Some(span) = pending.spans.get_mut(concat.seq as usize - 1) {
+            let start = pending.text.len();
+            for c in text.chars() {
+                if pending.text.push(c).is_err() {
+                    break;
+                }
+            }
+            *span = Some((start, pending.text.len()));
+        }
+
+        if pending.is_complete() {
+            return self.slots[index].take().map(Pending::assemble);
+        }
+        None
+    }
+
+    /// When the oldest incomplete message times out.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.slots.iter().flatten().map(|p| p.started + Self::timeout()).min()
+    }
+
+    /// Takes a message whose missing parts did not arrive in time.
+    pub fn expire(&mut self, now: Instant) -> Option<AssembledSms> {
+        let slot = self.slots.iter_mut().find(|slot| slot.as_ref().is_some_and(|p| now >= p.started + Self::timeout()))?;
+        slot.take().map(Pending::assemble)
+    }
+
+    fn timeout() -> Duration {
+        Duration::from_secs(SMS_CONCAT_TIMEOUT_SECONDS as u64)
+    }
+
+    /// The slot collecting this message, a free one, or the oldest one,
+    /// whose parts are given up.
+    fn slot_for(&mut self, sender: &str, concat: Concat, now: Instant) -> usize {
+        let matching = self.slots.iter().position(|slot| {
+            slot.as_ref().is_some_and(|p| {
+                p.sender == sender && p.reference == concat.reference && p.total == concat.total
+            })
+        });
+        if let Some(index) = matching {
+            return index;
+        }
+
+        let index = self.slots.iter().position(Option::is_none).unwrap_or_else(|| {
+            warn!("Too many concatenated SMS in progress, dropping the oldest");
+            (0..SMS_CONCAT_SLOTS)
+                .min_by_key(|&i| self.slots[i].as_ref().map(|p| p.started))
+                .unwrap_or(0)
+        });
+        let mut owner = String::new();
+        let _ = owner.push_str(sender);
+        self.slots[index] = Some(Pending {
+            sender: owner,
+            reference: concat.reference,
+            total: concat.total,
+            received: 0,
+            spans: [None; SMS_MAX_PARTS],
+            text: String::new(),
+            started: now,
+        });
+        index
+    }
+}
+
+impl Default for SmsAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(reference: u16, total: u8, seq: u8) -> Concat {
+        Concat { reference, total, seq }
+    }
+
+    #[test]
+    fn parts_are_joined_in_order() {
+        let mut assembler = SmsAssembler::new();
+        let now = Instant::from_secs(0);
+        assert_eq!(assembler.push("+79001234567", part(7, 3, 3), "!", now), None);
+        assert_eq!(assembler.push("+79001234567", part(7, 3, 1), "Hello, ", now), None);
+        // A duplicate is ignored.
+        assert_eq!(assembler.push("+79001234567", part(7, 3, 1), "Hello, ", now), None);
+        // Same reference from someone else is another message.
+        assert_eq!(assembler.push("+79007654321", part(7, 3, 2), "there", now), None);
+
+        let sms = assembler.push("+79001234567", part(7, 3, 2), "world", now).unwrap();
+        assert_eq!(sms.sender, "+79001234567");
+        assert_eq!(sms.text, "Hello, world!");
+        assert_eq!(assembler.deadline(), Some(now + Duration::from_secs(SMS_CONCAT_TIMEOUT_SECONDS as u64)));
+    }
+
+    #[test]
+    fn incomplete_message_is_handed_out_on_timeout() {
+        let mut assembler = SmsAssembler::new();
+        let start = Instant::from_secs(10);
+        assembler.push("+79001234567", part(1, 2, 2), "tail", start);
+        let late = start + Duration::from_secs(SMS_CONCAT_TIMEOUT_SECONDS as u64);
+        assert_eq!(assembler.expire(late - Duration::from_secs(1)), None);
+
+        let sms = assembler.expire(late).unwrap();
+        assert_eq!(sms.text, "tail");
+        assert_eq!(assembler.deadline(), None);
+    }
+}
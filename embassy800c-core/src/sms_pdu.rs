@@ -0,0 +1,622 @@
+// /embassy800c-core/src/sms_pdu.rs
+//! SMS transfer PDUs (GSM 03.40) as exchanged with the modem in PDU mode
+//! (`AT+CMGF=0`): SMS-SUBMIT for sending, SMS-DELIVER and SMS-STATUS-REPORT
+//! for receiving, with the GSM 03.38 default alphabet and UCS2 text.
+//! Longer texts are split into concatenated SMS (`split`, `Concat`).
+
+use heapless::String;
+
+use crate::constants::{MAX_PHONE_LENGTH, SMS_PDU_MAX_OCTETS};
+use crate::gsm_time_converter::GsmTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PduError {
+    /// Not an even number of hex digits.
+    Hex,
+    /// The PDU ended before a field it announced.
+    Truncated,
+    /// Compressed or 8-bit data, or a message type other than DELIVER and STATUS-REPORT.
+    Unsupported,
+    /// The text does not fit into a single SMS.
+    TooLong,
+    /// The destination is not a phone number.
+    InvalidNumber,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Alphabet {
+    /// GSM 03.38 default alphabet, 160 characters per SMS.
+    Gsm7,
+    /// 70 characters per SMS, for everything else (e.g. Cyrillic).
+    Ucs2,
+}
+
+impl Alphabet {
+    /// The denser alphabet able to carry `text`.
+    pub fn for_text(text: &str) -> Self {
+        if text.chars().all(|c| gsm7_septets(c).is_some()) {
+            Alphabet::Gsm7
+        } else {
+            Alphabet::Ucs2
+        }
+    }
+
+    /// Characters in a single SMS, and in each part of a concatenated one
+    /// (the user data header takes the rest).
+    fn capacity(self) -> (usize, usize) {
+        match self {
+            Alphabet::Gsm7 => (160, 153),
+            Alphabet::Ucs2 => (70, 67),
+        }
+    }
+
+    /// Septets or UTF-16 units taken by `c`.
+    fn cost(self, c: char) -> usize {
+        match self {
+            Alphabet::Gsm7 => match gsm7_septets(c) {
+                Some((_, Some(_))) => 2,
+                _ => 1,
+            },
+            Alphabet::Ucs2 => c.len_utf16(),
+        }
+    }
+
+    fn dcs(self) -> u8 {
+        match self {
+            Alphabet::Gsm7 => 0x00,
+            Alphabet::Ucs2 => 0x08,
+        }
+    }
+
+    fn from_dcs(dcs: u8) -> Result<Self, PduError> {
+        match dcs >> 4 {
+            // General data coding; bit 5 marks compressed text.
+            0x0..=0x3 if dcs & 0x20 != 0 => Err(PduError::Unsupported),
+            0x0..=0x3 => match (dcs >> 2) & 0x03 {
+                0 | 3 => Ok(Alphabet::Gsm7),
+                2 => Ok(Alphabet::Ucs2),
+                _ => Err(PduError::Unsupported),
+            },
+            // Message waiting indication groups.
+            0xC | 0xD => Ok(Alphabet::Gsm7),
+            0xE => Ok(Alphabet::Ucs2),
+            0xF if dcs & 0x04 == 0 => Ok(Alphabet::Gsm7),
+            0xF => Err(PduError::Unsupported),
+            // Reserved groups are to be read as the default alphabet.
+            _ => Ok(Alphabet::Gsm7),
+        }
+    }
+}
+
+/// Part `seq` of `total` of a concatenated SMS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Concat {
+    /// Same for all parts of a message from one sender.
+    pub reference: u16,
+    pub total: u8,
+    /// 1-based.
+    pub seq: u8,
+}
+
+impl Concat {
+    fn from_header(header: &[u8]) -> Option<Self> {
+        let mut rest = header;
+        while let [iei, len, tail @ ..] = rest {
+            let (data, next) = tail.split_at_checked(*len as usize)?;
+            let concat = match (*iei, data) {
+                (IEI_CONCAT_8BIT, &[reference, total, seq]) => Some(Concat { reference: reference.into(), total, seq }),
+                (IEI_CONCAT_16BIT, &[high, low, total, seq]) => {
+                    Some(Concat { reference: u16::from_be_bytes([high, low]), total, seq })
+                }
+                _ => None,
+            };
+            if let Some(concat) = concat {
+                return (concat.seq >= 1 && concat.seq <= concat.total).then_some(concat);
+            }
+            rest = next;
+        }
+        None
+    }
+}
+
+/// An incoming SMS. Characters beyond the capacity of `text` are dropped.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Deliver<const N: usize> {
+    /// International (`+7...`), national or alphanumeric (`Beeline`) sender.
+    pub originator: String<MAX_PHONE_LENGTH>,
+    /// Service centre time stamp, local time of the network.
+    pub timestamp: GsmTime,
+    pub text: String<N>,
+    /// Set for a part of a concatenated SMS.
+    pub concat: Option<Concat>,
+}
+
+/// Outcome of an SMS sent with a status report request.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StatusReport {
+    /// Message reference given by `+CMGS` when the SMS was sent.
+    pub reference: u8,
+    pub recipient: String<MAX_PHONE_LENGTH>,
+    /// TP-Status: 0x00..=0x1F delivered, 0x20..=0x3F still trying,
+    /// anything above failed permanently.
+    pub status: u8,
+}
+
+impl StatusReport {
+    pub fn is_delivered(&self) -> bool {
+        self.status < 0x20
+    }
+
+    /// The service centre is still trying; another report follows.
+    pub fn is_pending(&self) -> bool {
+        (0x20..0x40).contains(&self.status)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Pdu<const N: usize> {
+    Deliver(Deliver<N>),
+    StatusReport(StatusReport),
+}
+
+const MTI_DELIVER: u8 = 0x00;
+const MTI_SUBMIT: u8 = 0x01;
+const MTI_STATUS_REPORT: u8 = 0x02;
+/// TP-UDHI, a user data header precedes the text.
+const UDHI: u8 = 0x40;
+/// TP-SRR, request a status report.
+const SRR: u8 = 0x20;
+/// TP-VPF, relative validity period present.
+const VPF_RELATIVE: u8 = 0x10;
+/// Validity period of 24 hours, as set by `AT+CSMP=49,167`.
+const VALIDITY_24H: u8 = 167;
+
+/// Concatenated SMS information elements, with 8 and 16-bit references.
+const IEI_CONCAT_8BIT: u8 = 0x00;
+const IEI_CONCAT_16BIT: u8 = 0x08;
+
+const TOA_INTERNATIONAL: u8 = 0x91;
+const TOA_UNKNOWN: u8 = 0x81;
+const TOA_ALPHANUMERIC: u8 = 0x50;
+
+const GSM7_ESCAPE: u8 = 0x1B;
+
+/// GSM 03.38 default alphabet; 0x1B escapes to `GSM7_EXTENSION`.
+const GSM7_BASIC: [char; 128] = [
+    '@', '£', '$', '¥', 'è', 'é', 'ù', 'ì', 'ò', 'Ç', '\n', 'Ø', 'ø', '\r', 'Å', 'å',
+    'Δ', '_', 'Φ', 'Γ', 'Λ', 'Ω', 'Π', 'Ψ', 'Σ', 'Θ', 'Ξ', '\u{1B}', 'Æ', 'æ', 'ß', 'É',
+    ' ', '!', '"', '#', '¤', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/',
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?',
+    '¡', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'Ä', 'Ö', 'Ñ', 'Ü', '§',
+    '¿', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'ä', 'ö', 'ñ', 'ü', 'à',
+];
+
+const GSM7_EXTENSION: [(u8, char); 10] = [
+    (0x0A, '\x0C'), (0x14, '^'), (0x28, '{'), (0x29, '}'), (0x2F, '\\'),
+    (0x3C, '['), (0x3D, '~'), (0x3E, ']'), (0x40, '|'), (0x65, '€'),
+];
+
+/// Septets of `c` in the default alphabet: one, or the escape and one.
+fn gsm7_septets(c: char) -> Option<(u8, Option<u8>)> {
+    // 0x1B is the escape, not a character.
+    if let Some(i) = GSM7_BASIC.iter().position(|&g| g == c).filter(|&i| i != GSM7_ESCAPE as usize) {
+        return Some((i as u8, None));
+    }
+    GSM7_EXTENSION.iter().find(|&&(_, g)| g == c).map(|&(code, _)| (GSM7_ESCAPE, Some(code)))
+}
+
+/// Septet `index` of user data packed from bit `start` on.
+fn septet_at(data: &[u8], start: usize, index: usize) -> Option<u8> {
+    let bit = start + index * 7;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let mut value = u16::from(*data.get(byte)?) >> shift;
+    if shift > 1 {
+        value |= u16::from(*data.get(byte + 1)?) << (8 - shift);
+    }
+    Some((value & 0x7F) as u8)
+}
+
+fn put_septet(out: &mut [u8], start: usize, index: usize, septet: u8) {
+    let bit = start + index * 7;
+    let (byte, shift) = (bit / 8, bit % 8);
+    out[byte] |= septet << shift;
+    if shift > 1 {
+        out[byte + 1] |= septet >> (8 - shift);
+    }
+}
+
+/// Unpacks `count` septets into text, resolving escapes.
+fn decode_gsm7<const N: usize>(data: &[u8], start: usize, count: usize) -> Result<String<N>, PduError> {
+    let mut text = String::new();
+    let mut escaped = false;
+    for i in 0..count {
+        let septet = septet_at(data, start, i).ok_or(PduError::Truncated)?;
+        let c = if escaped {
+            escaped = false;
+            // Unknown extensions fall back to the basic character.
+            GSM7_EXTENSION
+                .iter()
+                .find(|&&(code, _)| code == septet)
+                .map_or(GSM7_BASIC[septet as usize], |&(_, c)| c)
+        } else if septet == GSM7_ESCAPE {
+            escaped = true;
+            continue;
+        } else {
+            GSM7_BASIC[septet as usize]
+        };
+        let _ = text.push(c);
+    }
+    Ok(text)
+}
+
+fn decode_ucs2<const N: usize>(data: &[u8]) -> String<N> {
+    let units = data.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+    let mut text = String::new();
+    for c in char::decode_utf16(units) {
+        let _ = text.push(c.unwrap_or('?'));
+    }
+    text
+}
+
+/// Reads the fields of a PDU one after the other.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, PduError> {
+        let b = *self.data.get(self.pos).ok_or(PduError::Truncated)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PduError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(PduError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// `<digits>,<type>,<semi-octets>`; alphanumeric addresses are packed
+    /// GSM 7-bit text.
+    fn address(&mut self) -> Result<String<MAX_PHONE_LENGTH>, PduError> {
+        let digits = self.byte()? as usize;
+        let toa = self.byte()?;
+        let value = self.take(digits.div_ceil(2))?;
+        if toa & 0x70 == TOA_ALPHANUMERIC {
+            let mut name: String<MAX_PHONE_LENGTH> = decode_gsm7(value, 0, digits * 4 / 7)?;
+            // Padding bits of a full last octet read as '@'.
+            while name.ends_with('@') {
+                name.pop();
+            }
+            return Ok(name);
+        }
+
+        let mut number = String::new();
+        if toa == TOA_INTERNATIONAL {
+            let _ = number.push('+');
+        }
+        for i in 0..digits {
+            let nibble = (value[i / 2] >> (4 * (i % 2))) & 0x0F;
+            let c = match nibble {
+                0..=9 => (b'0' + nibble) as char,
+                0xA => '*',
+                0xB => '#',
+                _ => continue,
+            };
+            let _ = number.push(c);
+        }
+        Ok(number)
+    }
+
+    /// Service centre time stamp, seven swapped BCD octets; the time zone is dropped.
+    fn timestamp(&mut self) -> Result<GsmTime, PduError> {
+        let octets = self.take(7)?;
+        let bcd = |i: usize| (octets[i] & 0x0F) * 10 + (octets[i] >> 4);
+        Ok(GsmTime { year: bcd(0), month: bcd(1), day: bcd(2), hour: bcd(3), minute: bcd(4), second: bcd(5) })
+    }
+}
+
+/// Decodes a PDU received with `+CMT`, `+CDS` or `+CMGL`, given in hex
+/// and led by the service centre address.
+pub fn decode<const N: usize>(hex: &str) -> Result<Pdu<N>, PduError> {
+    let mut buf = [0u8; SMS_PDU_MAX_OCTETS];
+    let data = hex_to_bytes(hex.trim(), &mut buf)?;
+    let mut r = Reader { data, pos: 0 };
+    let smsc_len = r.byte()? as usize;
+    r.take(smsc_len)?;
+
+    let first = r.byte()?;
+    match first & 0x03 {
+        MTI_DELIVER => decode_deliver(&mut r, first).map(Pdu::Deliver),
+        MTI_STATUS_REPORT => {
+            let reference = r.byte()?;
+            let recipient = r.address()?;
+            r.timestamp()?;
+            r.timestamp()?;
+            let status = r.byte()?;
+            Ok(Pdu::StatusReport(StatusReport { reference, recipient, status }))
+        }
+        _ => Err(PduError::Unsupported),
+    }
+}
+
+fn decode_deliver<const N: usize>(r: &mut Reader<'_>, first: u8) -> Result<Deliver<N>, PduError> {
+    let originator = r.address()?;
+    let _pid = r.byte()?;
+    let alphabet = Alphabet::from_dcs(r.byte()?)?;
+    let timestamp = r.timestamp()?;
+    let udl = r.byte()? as usize;
+    let user_data = &r.data[r.pos..];
+
+    // For 7-bit text the header counts into the septets.
+    let header_len = if first & UDHI != 0 {
+        1 + *user_data.first().ok_or(PduError::Truncated)? as usize
+    } else {
+        0
+    };
+    let header = user_data.get(1..header_len.max(1)).ok_or(PduError::Truncated)?;
+    let concat = Concat::from_header(header);
+    let text = match alphabet {
+        Alphabet::Gsm7 => {
+            let header_septets = (header_len * 8).div_ceil(7);
+            let count = udl.checked_sub(header_septets).ok_or(PduError::Truncated)?;
+            decode_gsm7(user_data, header_septets * 7, count)?
+        }
+        Alphabet::Ucs2 => {
+            let body = user_data.get(header_len..udl).ok_or(PduError::Truncated)?;
+            decode_ucs2(body)
+        }
+    };
+    Ok(Deliver { originator, timestamp, text, concat })
+}
+
+/// Splits `text` into the parts of a concatenated SMS, or yields it whole
+/// if it fits into one.
+pub fn split(text: &str) -> Split<'_> {
+    let alphabet = Alphabet::for_text(text);
+    let (single, part) = alphabet.capacity();
+    let length: usize = text.chars().map(|c| alphabet.cost(c)).sum();
+    let limit = if length <= single { single } else { part };
+    Split { rest: Some(text), alphabet, limit }
+}
+
+pub struct Split<'a> {
+    rest: Option<&'a str>,
+    alphabet: Alphabet,
+    limit: usize,
+}
+
+impl<'a> Iterator for Split<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest?;
+        let mut used = 0;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                used += self.alphabet.cost(c);
+                used > self.limit
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        self.rest = (end < rest.len()).then(|| &rest[end..]);
+        Some(&rest[..end])
+    }
+}
+
+/// Encodes an SMS-SUBMIT to `number` (international with `+`, or national)
+/// into `out` and returns its length, the `<length>` of `AT+CMGS`. The
+/// service centre address is not part of it. `text` must fit one SMS, or
+/// one part of a concatenated one with `concat`.
+pub fn encode_submit(
+    number: &str,
+    text: &str,
+    status_report: bool,
+    concat: Option<Concat>,
+    out: &mut [u8],
+) -> Result<usize, PduError> {
+    let alphabet = Alphabet::for_text(text);
+    let mut w = Writer { out, pos: 0 };
+
+    let mut first = MTI_SUBMIT | VPF_RELATIVE | if status_report { SRR } else { 0 };
+    if concat.is_some() {
+        first |= UDHI;
+    }
+    w.byte(first)?;
+    // Message reference, assigned by the module.
+    w.byte(0)?;
+    w.address(number)?;
+    w.byte(0)?;
+    w.byte(alphabet.dcs())?;
+    w.byte(VALIDITY_24H)?;
+
+    let mut header = [0u8; 6];
+    let header = match concat {
+        Some(Concat { reference, total, seq }) => {
+            header = [5, IEI_CONCAT_8BIT, 3, reference as u8, total, seq];
+            &header[..]
+        }
+        None => &header[..0],
+    };
+    let (single, part) = alphabet.capacity();
+    let capacity = if concat.is_some() { part } else { single };
+    let length: usize = text.chars().map(|c| alphabet.cost(c)).sum();
+    if length > capacity {
+        return Err(PduError::TooLong);
+    }
+
+    match alphabet {
+        Alphabet::Gsm7 => {
+            // The text starts at the septet boundary after the header.
+            let header_septets = (header.len() * 8).div_ceil(7);
+            let septets = header_septets + length;
+            w.byte(septets as u8)?;
+            let data = w.space((septets * 7).div_ceil(8))?;
+            data.fill(0);
+            data[..header.len()].copy_from_slice(header);
+            let mut i = 0;
+            for (septet, ext) in text.chars().filter_map(gsm7_septets) {
+                put_septet(data, header_septets * 7, i, septet);
+                i += 1;
+                if let Some(ext) = ext {
+                    put_septet(data, header_septets * 7, i, ext);
+                    i += 1;
+                }
+            }
+        }
+        Alphabet::Ucs2 => {
+            w.byte((header.len() + length * 2) as u8)?;
+            for &b in header {
+                w.byte(b)?;
+            }
+            for unit in text.encode_utf16() {
+                w.byte((unit >> 8) as u8)?;
+                w.byte(unit as u8)?;
+            }
+        }
+    }
+    Ok(w.pos)
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn byte(&mut self, b: u8) -> Result<(), PduError> {
+        *self.out.get_mut(self.pos).ok_or(PduError::TooLong)? = b;
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn space(&mut self, len: usize) -> Result<&mut [u8], PduError> {
+        let start = self.pos;
+        self.pos += len;
+        self.out.get_mut(start..start + len).ok_or(PduError::TooLong)
+    }
+
+    fn address(&mut self, number: &str) -> Result<(), PduError> {
+        let (toa, digits) = match number.strip_prefix('+') {
+            Some(digits) => (TOA_INTERNATIONAL, digits),
+            None => (TOA_UNKNOWN, number),
+        };
+        if digits.is_empty() || digits.len() > 20 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(PduError::InvalidNumber);
+        }
+        self.byte(digits.len() as u8)?;
+        self.byte(toa)?;
+        for pair in digits.as_bytes().chunks(2) {
+            let low = pair[0] - b'0';
+            let high = pair.get(1).map_or(0x0F, |d| d - b'0');
+            self.byte(high << 4 | low)?;
+        }
+        Ok(())
+    }
+}
+
+fn hex_to_bytes<'a>(hex: &str, buf: &'a mut [u8]) -> Result<&'a [u8], PduError> {
+    let hex = hex.as_bytes();
+    if !hex.len().is_multiple_of(2) {
+        return Err(PduError::Hex);
+    }
+    let len = hex.len() / 2;
+    let out = buf.get_mut(..len).ok_or(PduError::TooLong)?;
+    for (b, pair) in out.iter_mut().zip(hex.chunks(2)) {
+        let pair = str::from_utf8(pair).map_err(|_| PduError::Hex)?;
+        *b = u8::from_str_radix(pair, 16).map_err(|_| PduError::Hex)?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submit(number: &str, text: &str, status_report: bool, expected: &str) {
+        let mut out = [0u8; SMS_PDU_MAX_OCTETS];
+        let len = encode_submit(number, text, status_report, None, &mut out).unwrap();
+        let mut buf = [0u8; SMS_PDU_MAX_OCTETS];
+        assert_eq!(&out[..len], hex_to_bytes(expected, &mut buf).unwrap());
+    }
+
+    #[test]
+    fn submit_packs_gsm7_text() {
+        submit("+79001234567", "hellohello", true, "31000B919700214365F70000A70AE8329BFD4697D9EC37");
+    }
+
+    #[test]
+    fn submit_falls_back_to_ucs2() {
+        submit("89001234567", "Тест", false, "11000B819800214365F70008A7080422043504410442");
+        let mut out = [0u8; SMS_PDU_MAX_OCTETS];
+        assert_eq!(encode_submit("+7900", &"Ж".repeat(71), false, None, &mut out), Err(PduError::TooLong));
+        assert_eq!(encode_submit("ADMIN", "x", false, None, &mut out), Err(PduError::InvalidNumber));
+    }
+
+    #[test]
+    fn deliver_in_ucs2_from_international_number() {
+        let pdu = "07919730071111F1040B919700214365F70008621090321554210E041F0420041E0412041504200410";
+        let Ok(Pdu::Deliver(sms)) = decode::<64>(pdu) else { panic!() };
+        assert_eq!(sms.originator, "+79001234567");
+        assert_eq!(sms.text, "ПРОВЕРА");
+        assert_eq!(
+            sms.timestamp,
+            GsmTime { year: 26, month: 1, day: 9, hour: 23, minute: 51, second: 45 }
+        );
+    }
+
+    #[test]
+    fn deliver_in_gsm7_from_alphanumeric_sender() {
+        // "Beeline" as sender, "{x}" with extension characters as text.
+        let pdu = "07919730071111F1040ED0C272999D769701000062109032155421051B147E9302";
+        let Ok(Pdu::Deliver(sms)) = decode::<64>(pdu) else { panic!() };
+        assert_eq!(sms.originator, "Beeline");
+        assert_eq!(sms.text, "{x}");
+        assert_eq!(sms.concat, None);
+    }
+
+    #[test]
+    fn long_text_is_split_and_parts_carry_a_header() {
+        let text = "A".repeat(200);
+        assert!(split(&text).map(str::len).eq([153, 47]));
+        assert_eq!(split("short").count(), 1);
+        assert_eq!(split(&"Ж".repeat(70)).count(), 1);
+        assert!(split(&"Ж".repeat(71)).map(|p| p.chars().count()).eq([67, 4]));
+
+        let mut out = [0u8; SMS_PDU_MAX_OCTETS];
+        let concat = Some(Concat { reference: 0x42, total: 2, seq: 2 });
+        let len = encode_submit("+79001234567", "hi", false, concat, &mut out).unwrap();
+        let mut buf = [0u8; SMS_PDU_MAX_OCTETS];
+        let expected = hex_to_bytes("51000B919700214365F70000A709050003420202D069", &mut buf).unwrap();
+        assert_eq!(&out[..len], expected);
+    }
+
+    #[test]
+    fn concatenated_deliver_part() {
+        // UCS2 part 1 of 2, 16-bit reference 0x1234.
+        let pdu = "07919730071111F1440B919700214365F70008621090321554210B060804123402010414041E";
+        let Ok(Pdu::Deliver(sms)) = decode::<64>(pdu) else { panic!() };
+        assert_eq!(sms.text, "ДО");
+        assert_eq!(sms.concat, Some(Concat { reference: 0x1234, total: 2, seq: 1 }));
+    }
+
+    #[test]
+    fn status_report() {
+        let pdu = "07919730071111F106170B919700214365F7621090321554216210903215742100";
+        let Ok(Pdu::StatusReport(report)) = decode::<64>(pdu) else { panic!() };
+        assert_eq!(report.reference, 0x17);
+        assert_eq!(report.recipient, "+79001234567");
+        assert!(report.is_delivered());
+        assert_eq!(decode::<64>("0791973007111"), Err(PduError::Hex));
+        assert_eq!(decode::<64>("07919730071111F104"), Err(PduError::Truncated));
+    }
+}
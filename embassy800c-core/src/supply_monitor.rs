@@ -0,0 +1,74 @@
+// /embassy800c-core/src/supply_monitor.rs
+//! Low supply voltage detection with hysteresis, so a battery hovering
+//! around the threshold (or sagging during 2 A transmit bursts) does not
+//! flood the recipient with reports.
+
+use crate::constants::{SUPPLY_LOW_MILLIVOLTS, SUPPLY_RESTORED_MILLIVOLTS};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SupplyChange {
+    /// Dropped below `SUPPLY_LOW_MILLIVOLTS`.
+    Low,
+    /// Back at or above `SUPPLY_RESTORED_MILLIVOLTS`.
+    Restored,
+}
+
+pub struct SupplyMonitor {
+    low: bool,
+}
+
+impl SupplyMonitor {
+    pub const fn new() -> Self {
+        Self { low: false }
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.low
+    }
+
+    /// Feeds a voltage reading, returning the change it caused if any.
+    pub fn update(&mut self, millivolts: u16) -> Option<SupplyChange> {
+        if !self.low && millivolts < SUPPLY_LOW_MILLIVOLTS {
+            self.low = true;
+            Some(SupplyChange::Low)
+        } else if self.low && millivolts >= SUPPLY_RESTORED_MILLIVOLTS {
+            self.low = false;
+            Some(SupplyChange::Restored)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for SupplyMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_each_crossing_once() {
+        let mut monitor = SupplyMonitor::new();
+        assert_eq!(monitor.update(4000), None);
+        assert_eq!(monitor.update(SUPPLY_LOW_MILLIVOLTS - 1), Some(SupplyChange::Low));
+        assert_eq!(monitor.update(SUPPLY_LOW_MILLIVOLTS - 100), None);
+        assert!(monitor.is_low());
+        assert_eq!(monitor.update(SUPPLY_RESTORED_MILLIVOLTS), Some(SupplyChange::Restored));
+        assert_eq!(monitor.update(4100), None);
+    }
+
+    #[test]
+    fn hysteresis_band_keeps_state() {
+        let mut monitor = SupplyMonitor::new();
+        assert_eq!(monitor.update(SUPPLY_LOW_MILLIVOLTS), None);
+        assert_eq!(monitor.update(SUPPLY_LOW_MILLIVOLTS - 1), Some(SupplyChange::Low));
+        // Recovering into the band between the thresholds is not enough.
+        assert_eq!(monitor.update(SUPPLY_RESTORED_MILLIVOLTS - 1), None);
+        assert!(monitor.is_low());
+    }
+}
@@ -0,0 +1,238 @@
+// /embassy800c-core/tests/cmux.rs
+//! `Cmux` against a stand-in module speaking GSM 07.10 over in-memory pipes.
+
+mod modem_sim;
+
+use std::convert::Infallible;
+
+use embassy800c_core::cmux::{
+    encode_frame, Cmux, CmuxError, FrameDecoder, FrameKind, LinkState, CMUX_AT_DLCI, CMUX_AUX_DLCI,
+    CMUX_CONTROL_DLCI,
+};
+use embassy800c_core::constants::CMUX_CHANNEL_BUFFER_SIZE;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::pipe::Pipe;
+use embassy_time::{Duration, Timer};
+use embedded_io_async::{ErrorType, Read, Write};
+use modem_sim::block_on;
+
+type Wire = Pipe<NoopRawMutex, 512>;
+
+/// One direction of the UART.
+struct End<'a>(&'a Wire);
+
+impl ErrorType for End<'_> {
+    type Error = Infallible;
+}
+
+impl Write for End<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        Ok(self.0.write(buf).await)
+    }
+
+    async fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl Read for End<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        Ok(self.0.read(buf).await)
+    }
+}
+
+/// Module side: answers SABM with UA (or DM for `refused`) and records the
+/// UIH payloads of the virtual channels.
+async fn module(from_mcu: &Wire, to_mcu: &Wire, refused: Option<u8>, received: &mut Vec<(u8, Vec<u8>)>) {
+    let mut decoder = FrameDecoder::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let n = from_mcu.read(&mut buf).await;
+        for &b in &buf[..n] {
+            let Some(frame) = decoder.push(b) else { continue };
+            let mut out = [0u8; 133];
+            match frame.kind {
+                FrameKind::Sabm => {
+                    let answer = if refused == Some(frame.dlci) { FrameKind::Dm } else { FrameKind::Ua };
+                    let len = encode_frame(frame.dlci, answer, &[], &mut out);
+                    to_mcu.write_all(&out[..len]).await;
+                }
+                FrameKind::Uih if frame.dlci != CMUX_CONTROL_DLCI => {
+                    received.push((frame.dlci, frame.info.to_vec()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn uih(dlci: u8, info: &[u8]) -> Vec<u8> {
+    let mut out = [0u8; 133];
+    let len = encode_frame(dlci, FrameKind::Uih, info, &mut out);
+    out[..len].to_vec()
+}
+
+#[test]
+fn plain_link_passes_at_channel_through() {
+    let to_module = Wire::new();
+    let to_mcu = Wire::new();
+    let cmux = Cmux::<NoopRawMutex, _>::new(End(&to_module));
+    let (mut tx, mut rx) = cmux.port(CMUX_AT_DLCI);
+    let (mut aux_tx, _) = cmux.port(CMUX_AUX_DLCI);
+
+    let (written, read, aux) = block_on(async {
+        let test = async {
+            tx.write_all(b"AT\r\n").await.unwrap();
+            to_mcu.write_all(b"\r\nOK\r\n").await;
+            let mut buf = [0u8; 16];
+            let n = rx.read(&mut buf).await.unwrap();
+            let mut written = [0u8; 4];
+            to_module.read(&mut written).await;
+            (written, buf[..n].to_vec(), aux_tx.write(b"AT\r\n").await)
+        };
+        match select(cmux.run(End(&to_mcu)), test).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(result) => result,
+        }
+    });
+
+    assert_eq!(&written, b"AT\r\n");
+    assert_eq!(read, b"\r\nOK\r\n");
+    assert_eq!(aux, Err(CmuxError::Closed));
+}
+
+#[test]
+fn at_channel_holds_back_data_its_reader_has_no_room_for() {
+    let to_module = Wire::new();
+    let to_mcu = Wire::new();
+    let cmux = Cmux::<NoopRawMutex, _>::new(End(&to_module));
+    let (_, mut rx) = cmux.port(CMUX_AT_DLCI);
+    let sent: Vec<u8> = (0..3 * CMUX_CHANNEL_BUFFER_SIZE).map(|i| b'0' + (i % 10) as u8).collect();
+
+    let read = block_on(async {
+        let test = async {
+            to_mcu.write_all(&sent).await;
+            // Let the multiplexer fill the channel before anything is read.
+            Timer::after(Duration::from_millis(50)).await;
+            let mut read = Vec::new();
+            while read.len() < sent.len() {
+                let mut buf = [0u8; 64];
+                let n = rx.read(&mut buf).await.unwrap();
+                read.extend_from_slice(&buf[..n]);
+            }
+            read
+        };
+        match select(cmux.run(End(&to_mcu)), test).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(result) => result,
+        }
+    });
+
+    assert_eq!(read, sent);
+}
+
+#[test]
+fn channels_are_opened_and_demultiplexed() {
+    let to_module = Wire::new();
+    let to_mcu = Wire::new();
+    let cmux = Cmux::<NoopRawMutex, _>::new(End(&to_module));
+    let (mut at_tx, mut at_rx) = cmux.port(CMUX_AT_DLCI);
+    let (_, mut aux_rx) = cmux.port(CMUX_AUX_DLCI);
+    let mut received = Vec::new();
+
+    let (at, aux) = block_on(async {
+        let test = async {
+            cmux.set_multiplexed(true);
+            // Held back until the channels are open.
+            at_tx.write_all(b"AT+CSQ\r\n").await.unwrap();
+            to_mcu.write_all(&uih(CMUX_AUX_DLCI, b"\r\n+CUSD: 0\r\n")).await;
+            to_mcu.write_all(&uih(CMUX_AT_DLCI, b"\r\nOK\r\n")).await;
+            let mut at = [0u8; 16];
+            let n = at_rx.read(&mut at).await.unwrap();
+            let mut aux = [0u8; 16];
+            let m = aux_rx.read(&mut aux).await.unwrap();
+            (at[..n].to_vec(), aux[..m].to_vec())
+        };
+        let link = select(cmux.run(End(&to_mcu)), module(&to_module, &to_mcu, None, &mut received));
+        match select(link, test).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(result) => result,
+        }
+    });
+
+    assert_eq!(cmux.link_state(), LinkState::Multiplexed);
+    assert!(cmux.is_open(CMUX_AUX_DLCI));
+    assert_eq!(received, vec![(CMUX_AT_DLCI, b"AT+CSQ\r\n".to_vec())]);
+    assert_eq!(at, b"\r\nOK\r\n");
+    assert_eq!(aux, b"\r\n+CUSD: 0\r\n");
+}
+
+#[test]
+fn refused_aux_channel_leaves_the_at_channel_usable() {
+    let to_module = Wire::new();
+    let to_mcu = Wire::new();
+    let cmux = Cmux::<NoopRawMutex, _>::new(End(&to_module));
+    let (mut at_tx, _) = cmux.port(CMUX_AT_DLCI);
+    let (mut aux_tx, _) = cmux.port(CMUX_AUX_DLCI);
+    let mut received = Vec::new();
+
+    let aux = block_on(async {
+        let test = async {
+            cmux.set_multiplexed(true);
+            at_tx.write_all(b"AT\r\n").await.unwrap();
+            aux_tx.write(b"AT+CCLK?\r\n").await
+        };
+        let link = select(cmux.run(End(&to_mcu)), module(&to_module, &to_mcu, Some(CMUX_AUX_DLCI), &mut received));
+        match select(link, test).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(result) => result,
+        }
+    });
+
+    assert_eq!(aux, Err(CmuxError::Closed));
+    assert_eq!(cmux.link_state(), LinkState::Multiplexed);
+    assert!(cmux.is_open(CMUX_AT_DLCI));
+    assert!(!cmux.is_open(CMUX_AUX_DLCI));
+
+    cmux.set_multiplexed(false);
+    assert_eq!(cmux.link_state(), LinkState::Plain);
+    assert!(!cmux.is_open(CMUX_AT_DLCI));
+}
+
+#[test]
+fn unanswered_channels_fall_back_to_the_plain_link() {
+    let to_module = Wire::new();
+    let to_mcu = Wire::new();
+    let cmux = Cmux::<NoopRawMutex, _>::new(End(&to_module));
+    let (mut at_tx, _) = cmux.port(CMUX_AT_DLCI);
+
+    let written = block_on(async {
+        let test = async {
+            cmux.set_multiplexed(true);
+            // The module never answers the SABM; the command goes out plain.
+            at_tx.write_all(b"AT\r\n").await.unwrap();
+            let mut written = Vec::new();
+            while !written.ends_with(b"AT\r\n") {
+                let mut buf = [0u8; 64];
+                let n = to_module.read(&mut buf).await;
+                written.extend_from_slice(&buf[..n]);
+            }
+            written
+        };
+        match select(cmux.run(End(&to_mcu)), test).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(result) => result,
+        }
+    });
+
+    assert_eq!(cmux.link_state(), LinkState::Plain);
+    assert!(!cmux.is_open(CMUX_CONTROL_DLCI));
+    let mut sabm = [0u8; 6];
+    encode_frame(CMUX_CONTROL_DLCI, FrameKind::Sabm, &[], &mut sabm);
+    let mut expected = sabm.repeat(3);
+    // Close down, in case the module runs the multiplexer after all.
+    expected.extend_from_slice(&uih(CMUX_CONTROL_DLCI, &[0xC3, 0x01]));
+    expected.extend_from_slice(b"AT\r\n");
+    assert_eq!(written, expected);
+}
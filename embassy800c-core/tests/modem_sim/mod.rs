@@ -0,0 +1,548 @@
+// /embassy800c-core/tests/modem_sim/mod.rs
+//! Scripted SIM800C stand-in for driving `Sim800Driver` on the host.
+//!
+//! A script is a queue of steps. Each step either waits for the firmware to
+//! send a specific command line (`expect`), for the module to be switched on
+//! (`on_power_on`) or fires as soon as the previous step completed (`emit`),
+//! and then outputs lines, raw bytes and delays. Everything the firmware
+//! writes is recorded for byte-exact assertions.
+//!
+//! PWRKEY is modelled by `PowerControl::set_enable`: holding it high for at
+//! least `PWRKEY_MIN_PULSE` toggles the module on or off. A module that is
+//! off or hung ignores everything written to it.
+//!
+//! The UART rate is only modelled once a test sets it (`at_baud`): while the
+//! module and the UART disagree, input is lost and output arrives garbled.
+//! An autobauding module adopts the UART rate with the first `AT`.
+//!
+//! The control lines share a `ModemWake` like the firmware's do: `ring`
+//! stands for the ring indicator, and `wake` is what a second AT channel
+//! holds to keep the module awake.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use embassy800c_core::modem_wake::ModemWake;
+use embassy800c_core::sim800::PowerControl;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+const PWRKEY_MIN_PULSE: Duration = Duration::from_secs(1);
+const CTRL_Z: u8 = 0x1A;
+const ESC: u8 = 0x1B;
+
+enum Output {
+    Line(String),
+    Raw(Vec<u8>),
+    Delay(Duration),
+    Overrun,
+    Hang,
+    PowerDown,
+    Baud(u32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Trigger {
+    Command(String),
+    PowerOn,
+    /// Fires as soon as it reaches the front of the script.
+    Immediate,
+}
+
+struct Step {
+    trigger: Trigger,
+    output: Vec<Output>,
+}
+
+struct State {
+    script: VecDeque<Step>,
+    written: Vec<u8>,
+    line: Vec<u8>,
+    echo: bool,
+    sms_body: bool,
+    /// Scheduled receive chunks; `None` is a receiver overrun.
+    pending: VecDeque<(Instant, Option<Vec<u8>>)>,
+    cursor: Instant,
+    mismatches: Vec<String>,
+    waker: Option<Waker>,
+    /// Ignores all input until the module is powered off.
+    hung: bool,
+    power_cycles: usize,
+    powered: bool,
+    key_down: Option<Instant>,
+    ttl: bool,
+    dtr: bool,
+    /// Rate the module talks at, `None` while autobauding.
+    module_baud: Option<u32>,
+    /// The module returns to autobauding when switched off.
+    autobaud: bool,
+    uart_baud: Option<u32>,
+}
+
+impl State {
+    fn schedule(&mut self, bytes: Vec<u8>) {
+        self.schedule_chunk(Some(bytes));
+    }
+
+    fn baud_mismatch(&self) -> bool {
+        matches!((self.module_baud, self.uart_baud), (Some(module), Some(uart)) if module != uart)
+    }
+
+    /// Output of the module as the UART receives it.
+    fn schedule_output(&mut self, bytes: Vec<u8>) {
+        if self.baud_mismatch() {
+            self.schedule(vec![0xFF; bytes.len()]);
+        } else {
+            self.schedule(bytes);
+        }
+    }
+
+    fn schedule_chunk(&mut self, chunk: Option<Vec<u8>>) {
+        self.cursor = self.cursor.max(Instant::now());
+        self.pending.push_back((self.cursor, chunk));
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn run_step(&mut self, step: Step) {
+        for out in step.output {
+            match out {
+                Output::Line(line) => self.schedule_output(format!("\r\n{line}\r\n").into_bytes()),
+                Output::Raw(bytes) => self.schedule_output(bytes),
+                Output::Overrun => self.schedule_chunk(None),
+                Output::Hang => self.hung = true,
+                Output::PowerDown => self.switch_off(),
+                Output::Baud(rate) => {
+                    self.module_baud = Some(rate);
+                    self.autobaud = false;
+                }
+                Output::Delay(delay) => {
+                    self.cursor = self.cursor.max(Instant::now()) + delay;
+                }
+            }
+        }
+    }
+
+    fn advance(&mut self) {
+        while self.script.front().is_some_and(|step| step.trigger == Trigger::Immediate) {
+            let step = self.script.pop_front().unwrap();
+            self.run_step(step);
+        }
+    }
+
+    fn on_command(&mut self, cmd: String) {
+        if self.echo && !self.sms_body {
+            self.schedule(format!("{cmd}\r").into_bytes());
+        }
+        if cmd.eq_ignore_ascii_case("ATE0") {
+            self.echo = false;
+        }
+        if cmd.starts_with("AT+CMGS=") {
+            self.sms_body = true;
+        }
+
+        match self.script.front() {
+            Some(Step { trigger: Trigger::Command(expected), .. }) if *expected == cmd => {
+                let step = self.script.pop_front().unwrap();
+                self.run_step(step);
+                self.advance();
+            }
+            other => {
+                let expected = other.map(|s| s.trigger.clone());
+                self.mismatches.push(format!("expected {expected:?}, got {cmd:?}"));
+                self.sms_body = false;
+                self.schedule(b"\r\nERROR\r\n".to_vec());
+            }
+        }
+    }
+
+    fn switch_on(&mut self) {
+        self.powered = true;
+        self.echo = true;
+        match self.script.front() {
+            Some(Step { trigger: Trigger::PowerOn, .. }) => {
+                let step = self.script.pop_front().unwrap();
+                self.run_step(step);
+                self.advance();
+            }
+            other => {
+                let expected = other.map(|s| s.trigger.clone());
+                self.mismatches.push(format!("expected {expected:?}, got power on"));
+            }
+        }
+    }
+
+    /// Already scheduled output is still delivered.
+    fn switch_off(&mut self) {
+        self.powered = false;
+        self.power_cycles += 1;
+        self.hung = false;
+        self.sms_body = false;
+        self.line.clear();
+        if self.autobaud {
+            self.module_baud = None;
+        }
+    }
+
+    fn on_byte(&mut self, b: u8) {
+        self.written.push(b);
+        if self.hung || !self.powered || self.baud_mismatch() {
+            return;
+        }
+        if self.sms_body {
+            match b {
+                CTRL_Z | ESC => {
+                    let body = String::from_utf8_lossy(&self.line).into_owned();
+                    self.line.clear();
+                    self.on_command(body);
+                    self.sms_body = false;
+                }
+                _ => self.line.push(b),
+            }
+            return;
+        }
+        match b {
+            b'\r' | b'\n' => {
+                if self.module_baud.is_none() && self.line.starts_with(b"AT") {
+                    self.module_baud = self.uart_baud;
+                }
+                if !self.line.is_empty() {
+                    let cmd = String::from_utf8_lossy(&self.line).into_owned();
+                    self.line.clear();
+                    self.on_command(cmd);
+                }
+            }
+            _ => self.line.push(b),
+        }
+    }
+}
+
+/// Handle used by the test to script the modem and inspect the traffic.
+#[derive(Clone)]
+pub struct ModemSim {
+    state: Rc<RefCell<State>>,
+    wake: Rc<ModemWake<NoopRawMutex>>,
+}
+
+impl ModemSim {
+    pub fn new() -> Self {
+        Self {
+            state: Rc::new(RefCell::new(State {
+                script: VecDeque::new(),
+                written: Vec::new(),
+                line: Vec::new(),
+                echo: true,
+                sms_body: false,
+                pending: VecDeque::new(),
+                cursor: Instant::now(),
+                mismatches: Vec::new(),
+                waker: None,
+                hung: false,
+                power_cycles: 0,
+                powered: true,
+                key_down: None,
+                ttl: false,
+                dtr: false,
+                module_baud: None,
+                autobaud: false,
+                uart_baud: None,
+            })),
+            wake: Rc::new(ModemWake::new()),
+        }
+    }
+
+    /// UART halves and control lines to hand to `Sim800Driver::new`.
+    pub fn split(&self) -> (SimTx, SimRx, SimPower) {
+        (
+            SimTx(self.state.clone()),
+            SimRx(self.state.clone()),
+            SimPower(self.state.clone(), self.wake.clone()),
+        )
+    }
+
+    /// Starts with the module switched off.
+    pub fn powered_off(&self) -> &Self {
+        self.state.borrow_mut().powered = false;
+        self
+    }
+
+    /// Starts the echo disabled, as after a previous `ATE0`.
+    pub fn without_echo(&self) -> &Self {
+        self.state.borrow_mut().echo = false;
+        self
+    }
+
+    /// Starts the module fixed at `module` (autobauding for `None`) and the
+    /// UART at `uart`.
+    pub fn at_baud(&self, module: Option<u32>, uart: u32) -> &Self {
+        let mut state = self.state.borrow_mut();
+        state.module_baud = module;
+        state.autobaud = module.is_none();
+        state.uart_baud = Some(uart);
+        self
+    }
+
+    /// Adds a step waiting for the command line `cmd` (or SMS body).
+    pub fn expect(&self, cmd: &str) -> &Self {
+        self.push_step(Trigger::Command(cmd.to_string()))
+    }
+
+    /// Adds a step firing when a PWRKEY pulse switches the module on.
+    pub fn on_power_on(&self) -> &Self {
+        self.push_step(Trigger::PowerOn)
+    }
+
+    /// Adds a step firing as soon as all previous steps completed.
+    pub fn emit(&self) -> &Self {
+        self.push_step(Trigger::Immediate)
+    }
+
+    /// Outputs `\r\n<line>\r\n` as part of the last step.
+    pub fn line(&self, line: &str) -> &Self {
+        self.push_output(Output::Line(line.to_string()))
+    }
+
+    /// Outputs bytes verbatim as part of the last step.
+    pub fn raw(&self, bytes: &[u8]) -> &Self {
+        self.push_output(Output::Raw(bytes.to_vec()))
+    }
+
+    /// Delays the remaining output of the script.
+    pub fn delay(&self, delay: Duration) -> &Self {
+        self.push_output(Output::Delay(delay))
+    }
+
+    /// Makes the receiver fail once, as a UART/DMA overrun would.
+    pub fn overrun(&self) -> &Self {
+        self.push_output(Output::Overrun)
+    }
+
+    /// Stops reacting to input until the module is powered off.
+    pub fn hang(&self) -> &Self {
+        self.push_output(Output::Hang)
+    }
+
+    /// Switches the module off, as after `AT+CPOWD=1`.
+    pub fn power_down(&self) -> &Self {
+        self.push_output(Output::PowerDown)
+    }
+
+    /// Switches the module to `rate` after the output so far, as `AT+IPR` does.
+    pub fn baud(&self, rate: u32) -> &Self {
+        self.push_output(Output::Baud(rate))
+    }
+
+    /// Shorthand for `expect(cmd).line("OK")`.
+    pub fn ok(&self, cmd: &str) -> &Self {
+        self.expect(cmd).line("OK")
+    }
+
+    /// Everything the firmware has written so far.
+    pub fn written(&self) -> Vec<u8> {
+        self.state.borrow().written.clone()
+    }
+
+    pub fn written_str(&self) -> String {
+        String::from_utf8_lossy(&self.state.borrow().written).into_owned()
+    }
+
+    pub fn clear_written(&self) {
+        self.state.borrow_mut().written.clear();
+    }
+
+    pub fn powered(&self) -> bool {
+        self.state.borrow().powered
+    }
+
+    pub fn ttl(&self) -> bool {
+        self.state.borrow().ttl
+    }
+
+    /// DTR level; high lets a module set to `AT+CSCLK=1` sleep.
+    pub fn dtr(&self) -> bool {
+        self.state.borrow().dtr
+    }
+
+    /// Pulses the ring indicator.
+    pub fn ring(&self) {
+        self.wake.request();
+    }
+
+    /// Wake-up state shared with the driver's control lines.
+    pub fn wake(&self) -> &ModemWake<NoopRawMutex> {
+        &self.wake
+    }
+
+    /// Rate the firmware set the UART to.
+    pub fn uart_baud(&self) -> Option<u32> {
+        self.state.borrow().uart_baud
+    }
+
+    /// How often the module was switched off after being on.
+    pub fn power_cycles(&self) -> usize {
+        self.state.borrow().power_cycles
+    }
+
+    /// Panics if a command did not match the script or steps are left over.
+    pub fn assert_done(&self) {
+        let state = self.state.borrow();
+        assert!(state.mismatches.is_empty(), "script mismatches: {:#?}", state.mismatches);
+        let left: Vec<_> = state.script.iter().map(|s| s.trigger.clone()).collect();
+        assert!(state.key_down.is_none(), "PWRKEY left pressed");
+        assert!(left.is_empty(), "unconsumed script steps: {left:?}");
+    }
+
+    fn push_step(&self, trigger: Trigger) -> &Self {
+        let mut state = self.state.borrow_mut();
+        state.script.push_back(Step { trigger, output: Vec::new() });
+        self
+    }
+
+    fn push_output(&self, out: Output) -> &Self {
+        let mut state = self.state.borrow_mut();
+        state
+            .script
+            .back_mut()
+            .expect("output must follow expect() or emit()")
+            .output
+            .push(out);
+        self
+    }
+}
+
+pub struct SimTx(Rc<RefCell<State>>);
+pub struct SimRx(Rc<RefCell<State>>);
+pub struct SimPower(Rc<RefCell<State>>, Rc<ModemWake<NoopRawMutex>>);
+
+impl ErrorType for SimTx {
+    type Error = Infallible;
+}
+
+impl Write for SimTx {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let mut state = self.0.borrow_mut();
+        state.advance();
+        for &b in buf {
+            state.on_byte(b);
+        }
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ErrorType for SimRx {
+    type Error = ErrorKind;
+}
+
+impl Read for SimRx {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        loop {
+            let due = poll_fn(|cx| {
+                let mut state = self.0.borrow_mut();
+                state.advance();
+                match state.pending.front() {
+                    Some((at, _)) => Poll::Ready(*at),
+                    None => {
+                        state.waker = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            })
+            .await;
+
+            if due > Instant::now() {
+                Timer::at(due).await;
+                continue;
+            }
+
+            let mut state = self.0.borrow_mut();
+            let Some(chunk) = state.pending.front_mut().unwrap().1.as_mut() else {
+                state.pending.pop_front();
+                return Err(ErrorKind::Other);
+            };
+            let n = buf.len().min(chunk.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            chunk.drain(..n);
+            if chunk.is_empty() {
+                state.pending.pop_front();
+            }
+            return Ok(n);
+        }
+    }
+}
+
+impl PowerControl for SimPower {
+    fn set_enable(&mut self, high: bool) {
+        let mut state = self.0.borrow_mut();
+        if high {
+            state.key_down.get_or_insert(Instant::now());
+            return;
+        }
+        let Some(pressed) = state.key_down.take() else {
+            return;
+        };
+        if Instant::now() - pressed >= PWRKEY_MIN_PULSE {
+            if state.powered {
+                state.switch_off();
+            } else {
+                state.switch_on();
+            }
+        }
+    }
+
+    fn set_ttl(&mut self, high: bool) {
+        self.0.borrow_mut().ttl = high;
+    }
+
+    fn set_dtr(&mut self, high: bool) {
+        self.0.borrow_mut().dtr = high;
+        self.1.set_asleep(high);
+    }
+
+    async fn wake_requested(&mut self) {
+        self.1.requested().await;
+    }
+
+    fn keep_awake(&self) -> bool {
+        self.1.is_held()
+    }
+
+    fn set_baud_rate(&mut self, baud: u32) {
+        self.0.borrow_mut().uart_baud = Some(baud);
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs a future to completion, parking the thread between polls so that
+/// tests waiting on timers do not spin.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return out;
+        }
+        thread::park();
+    }
+}
@@ -0,0 +1,963 @@
+// /embassy800c-core/tests/sim800_driver.rs
+//! End-to-end tests of `Sim800Driver` against the scripted modem simulator.
+
+mod modem_sim;
+
+use embassy800c_core::at_parser::RegistrationStatus;
+use embassy800c_core::sim800::{
+    Command, DeliveryStatus, ModemPowerState, PowerControl, PowerOnStage, RestartReason, Sim800Driver, Sim800Error, SimEvent,
+    SimFault,
+};
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Timer};
+use heapless::String;
+
+use modem_sim::{block_on, ModemSim, SimPower, SimRx, SimTx};
+
+const ADMIN: &str = "+79001234567";
+const ICCID: &str = "8970101234567890123";
+
+type Driver = Sim800Driver<SimTx, SimRx, SimPower>;
+
+/// Driver provisioned for the scripted SIM.
+fn driver(sim: &ModemSim) -> Driver {
+    let (tx, rx, power) = sim.split();
+    let mut drv = Sim800Driver::new(tx, rx, power);
+    let mut iccid = String::new();
+    iccid.push_str(ICCID).unwrap();
+    drv.set_provisioned_iccid(Some(iccid));
+    drv
+}
+
+/// Boot URCs after the PWRKEY pulse, the init sequence and the phone book.
+fn script_power_on(sim: &ModemSim) {
+    script_boot(sim);
+    script_configure(sim);
+}
+
+fn script_boot(sim: &ModemSim) {
+    sim.on_power_on()
+        .line("RDY")
+        .line("+CFUN: 1")
+        .line("+CPIN: READY")
+        .delay(Duration::from_millis(50))
+        .line("Call Ready")
+        .line("SMS Ready");
+}
+
+fn script_configure(sim: &ModemSim) {
+    script_configure_with_registration(sim, "+CREG: 1,1");
+}
+
+fn script_configure_with_registration(sim: &ModemSim, creg: &str) {
+    script_setup(sim, creg);
+    sim.expect("AT+CPMS?").line("+CPMS: \"SM\",0,50,\"SM\",0,50,\"SM\",0,50").line("OK");
+}
+
+/// Settings applied on power-on in text mode.
+const TEXT_MODE_SETTINGS: [&str; 10] = [
+    "AT", "ATE0", "AT+CMEE=1", "AT+CLIP=1", "AT+CMGF=1",
+    "AT+CSCS=\"GSM\"", "AT+CNMI=1,2,0,1,0", "AT+CSMP=49,167,0,0",
+    "AT+CREG=1", "AT+DDET=1",
+];
+
+/// Settings applied on power-on in PDU mode.
+const PDU_MODE_SETTINGS: [&str; 9] = [
+    "AT", "ATE0", "AT+CMEE=1", "AT+CLIP=1", "AT+CMGF=0",
+    "AT+CNMI=1,2,0,1,0", "AT+CSMP=49,167,0,0",
+    "AT+CREG=1", "AT+DDET=1",
+];
+
+/// Settings, identity and phone book, up to the stored SMS.
+fn script_setup(sim: &ModemSim, creg: &str) {
+    script_setup_with(sim, &TEXT_MODE_SETTINGS, creg);
+}
+
+fn script_setup_with(sim: &ModemSim, settings: &[&str], creg: &str) {
+    for cmd in settings {
+        sim.ok(cmd);
+    }
+    sim.expect("AT+CREG?").line(creg).line("OK");
+    sim.expect("AT+GSN").line("861234567890123").line("OK");
+    sim.expect("AT+CCID").line(&format!("{ICCID}F")).line("OK");
+    sim.expect("AT+CIMI").line("250011234567890").line("OK");
+    sim.expect("AT+GMR").line("Revision:1418B05SIM800C24").line("OK");
+    sim.expect("AT+CPBR=1")
+        .line(&format!("+CPBR: 1,\"{ADMIN}\",145,\"Admin\""))
+        .line("OK");
+    for i in 2..=8 {
+        sim.ok(&format!("AT+CPBR={i}"));
+    }
+}
+
+/// Module that is off and boots normally when switched on.
+fn booting_sim() -> ModemSim {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    script_power_on(&sim);
+    sim
+}
+
+/// Receives the event following the registration and `ModemReady` that end
+/// power-on in `run`.
+async fn after_ready<const N: usize>(events: &Channel<NoopRawMutex, SimEvent, N>) -> SimEvent {
+    assert_eq!(events.receive().await, SimEvent::NetworkStatus(RegistrationStatus::Home));
+    assert_eq!(events.receive().await, SimEvent::ModemReady);
+    events.receive().await
+}
+
+#[test]
+fn power_on_runs_init_sequence_and_loads_phone_book() {
+    let sim = booting_sim();
+    let mut drv = driver(&sim);
+
+    assert_eq!(block_on(drv.power_on()), Ok(()));
+
+    sim.assert_done();
+    assert!(sim.powered());
+    assert!(sim.ttl());
+    assert_eq!(drv.phone_book().get_first(), Some(ADMIN));
+    let identity = drv.identity();
+    assert_eq!(identity.imei.as_str(), "861234567890123");
+    assert_eq!(identity.iccid.as_str(), ICCID);
+    assert_eq!(identity.imsi.as_str(), "250011234567890");
+    assert_eq!(identity.revision.as_str(), "1418B05SIM800C24");
+    // The first `AT` probes for a module that is already running.
+    assert!(sim.written_str().starts_with("AT\r\nAT\r\nATE0\r\nAT+CMEE=1\r\n"));
+    assert!(sim.written_str().ends_with("AT+CPBR=7\r\nAT+CPBR=8\r\nAT+CPMS?\r\n"));
+}
+
+#[test]
+fn pdu_mode_power_on_leaves_the_character_set_alone() {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    script_boot(&sim);
+    script_setup_with(&sim, &PDU_MODE_SETTINGS, "+CREG: 1,1");
+    sim.expect("AT+CPMS?").line("+CPMS: \"SM\",0,50,\"SM\",0,50,\"SM\",0,50").line("OK");
+    let mut drv = driver(&sim);
+    drv.set_pdu_mode(true);
+
+    assert_eq!(block_on(drv.power_on()), Ok(()));
+
+    sim.assert_done();
+    assert!(!sim.written_str().contains("AT+CSCS"));
+}
+
+#[test]
+fn stored_sms_are_passed_on_and_deleted_at_power_on() {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    script_boot(&sim);
+    script_setup(&sim, "+CREG: 1,1");
+    sim.expect("AT+CPMS?").line("+CPMS: \"SM\",3,3,\"SM\",3,3,\"SM\",3,3").line("OK");
+    // The message text follows its header without a blank line.
+    sim.expect("AT+CMGL=\"ALL\",1")
+        .line("+CMGL: 1,\"REC READ\",\"+79001234567\",\"\",\"26/01/09,23:15:31+12\"")
+        .raw(b"PPP;000\r\n")
+        .line("+CMGL: 2,\"REC UNREAD\",\"+79001234567\",\"\",\"26/01/09,23:16:31+12\"")
+        .raw(b"PPP;101\r\n")
+        .line("+CMGL: 3,\"REC UNREAD\",\"+79007654321\",\"\",\"26/01/09,23:17:31+12\"")
+        .raw(b"BALANCE\r\n")
+        .line("OK");
+    sim.ok("AT+CMGD=1");
+    sim.ok("AT+CMGD=2");
+    sim.ok("AT+CMGD=3");
+    sim.ok("AT+CMGD=1,3");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+
+    let received = block_on(async {
+        let collect = async { [events.receive().await, events.receive().await, events.receive().await] };
+        match select(drv.run(commands.receiver(), events.sender()), collect).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    let sms = |number: &str, message: &str| SimEvent::SmsReceived {
+        number: number.try_into().unwrap(),
+        message: message.try_into().unwrap(),
+    };
+    assert_eq!(
+        received,
+        [
+            SimEvent::NetworkStatus(RegistrationStatus::Home),
+            sms(ADMIN, "PPP;101"),
+            sms("+79007654321", "BALANCE"),
+        ]
+    );
+}
+
+#[test]
+fn stored_sms_sweep_leaves_room_for_modem_ready() {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    script_boot(&sim);
+    script_setup(&sim, "+CREG: 1,1");
+    let stored = |sim: &ModemSim, first: u8| {
+        let mut script = sim.expect("AT+CMGL=\"ALL\",1");
+        for index in first..=4 {
+            script = script
+                .line(&format!("+CMGL: {index},\"REC UNREAD\",\"+79001234567\",\"\",\"26/01/09,23:15:31+12\""))
+                .raw(format!("PPP;10{index}\r\n").as_bytes());
+        }
+        script.line("OK");
+    };
+    sim.expect("AT+CPMS?").line("+CPMS: \"SM\",4,50,\"SM\",4,50,\"SM\",4,50").line("OK");
+    stored(&sim, 1);
+    sim.ok("AT+CMGD=1");
+    sim.ok("AT+CMGD=2");
+    // The rest is taken once the queue has been forwarded.
+    sim.expect("AT+CPMS?").line("+CPMS: \"SM\",2,50,\"SM\",2,50,\"SM\",2,50").line("OK");
+    stored(&sim, 3);
+    sim.ok("AT+CMGD=3");
+    sim.ok("AT+CMGD=4");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+
+    let received = block_on(async {
+        let collect = async {
+            let mut received = Vec::new();
+            for _ in 0..6 {
+                received.push(events.receive().await);
+            }
+            received
+        };
+        match select(drv.run(commands.receiver(), events.sender()), collect).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    let sms = |message: &str| SimEvent::SmsReceived {
+        number: ADMIN.try_into().unwrap(),
+        message: message.try_into().unwrap(),
+    };
+    assert_eq!(
+        received,
+        [
+            SimEvent::NetworkStatus(RegistrationStatus::Home),
+            sms("PPP;101"),
+            sms("PPP;102"),
+            SimEvent::ModemReady,
+            sms("PPP;103"),
+            sms("PPP;104"),
+        ]
+    );
+}
+
+#[test]
+fn send_sms_waits_for_prompt_and_ends_with_ctrl_z() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("AT+CMGS=\"+79001234567\"").raw(b"\r\n> ");
+    sim.expect("PPP_123_260109231531")
+        .delay(Duration::from_millis(50))
+        .line("+CMGS: 17")
+        .line("OK");
+    let mut drv = driver(&sim);
+
+    assert_eq!(block_on(drv.send_sms(ADMIN, "PPP_123_260109231531")), Ok(()));
+
+    sim.assert_done();
+    assert_eq!(
+        sim.written(),
+        b"AT+CMGS=\"+79001234567\"\rPPP_123_260109231531\x1a\r\n".to_vec()
+    );
+}
+
+#[test]
+fn send_sms_in_pdu_mode_goes_out_as_ucs2() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("AT+CMGS=22").raw(b"\r\n> ");
+    sim.expect("0031000B919700214365F70008A7080422043504410442")
+        .line("+CMGS: 18")
+        .line("OK");
+    let mut drv = driver(&sim);
+    drv.set_pdu_mode(true);
+
+    assert_eq!(block_on(drv.send_sms(ADMIN, "Тест")), Ok(()));
+    sim.assert_done();
+    assert!(sim.written().ends_with(b"0442\x1a\r\n"));
+}
+
+#[test]
+fn long_sms_goes_out_as_concatenated_parts() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("AT+CMGS=154").raw(b"\r\n> ");
+    sim.expect(&format!("0071000B919700214365F70008A78C050003010201{}", "0416".repeat(67)))
+        .line("+CMGS: 18")
+        .line("OK");
+    sim.expect("AT+CMGS=28").raw(b"\r\n> ");
+    sim.expect(&format!("0071000B919700214365F70008A70E050003010202{}", "0416".repeat(4)))
+        .line("+CMGS: 19")
+        .line("OK");
+    let mut drv = driver(&sim);
+    drv.set_pdu_mode(true);
+
+    assert_eq!(block_on(drv.send_sms(ADMIN, &"Ж".repeat(71))), Ok(()));
+    sim.assert_done();
+}
+
+#[test]
+fn long_message_command_goes_out_whole() {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    script_boot(&sim);
+    script_setup_with(&sim, &PDU_MODE_SETTINGS, "+CREG: 1,1");
+    sim.expect("AT+CPMS?").line("+CPMS: \"SM\",0,50,\"SM\",0,50,\"SM\",0,50").line("OK");
+    sim.expect("AT+CMGS=154").raw(b"\r\n> ");
+    sim.expect(&format!("0071000B919700214365F70008A78C050003010201{}", "0416".repeat(67)))
+        .line("+CMGS: 18")
+        .line("OK");
+    sim.expect("AT+CMGS=28").raw(b"\r\n> ");
+    sim.expect(&format!("0071000B919700214365F70008A70E050003010202{}", "0416".repeat(4)))
+        .line("+CMGS: 19")
+        .line("OK");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut phone_number = String::new();
+    phone_number.push_str(ADMIN).unwrap();
+    let mut message = String::new();
+    message.push_str(&"Ж".repeat(71)).unwrap();
+    commands.try_send(Command::SendMessage { phone_number, message }).unwrap();
+    let mut drv = driver(&sim);
+    drv.set_pdu_mode(true);
+
+    let sent = block_on(async {
+        match select(drv.run(commands.receiver(), events.sender()), after_ready(&events)).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    assert_eq!(sent, SimEvent::SmsSent(Ok(())));
+}
+
+#[test]
+fn alarm_sms_delivery_is_reported() {
+    let sim = booting_sim();
+    sim.expect("AT+CMGS=\"+79001234567\"").raw(b"\r\n> ");
+    sim.expect("PPP_123_260109231531")
+        .line("+CMGS: 17")
+        .line("OK")
+        .delay(Duration::from_millis(50))
+        .line("+CDS: 6,16,\"+79001234567\",145,\"26/01/09,23:15:31+12\",\"26/01/09,23:15:35+12\",0")
+        .line("+CDS: 6,17,\"+79001234567\",145,\"26/01/09,23:15:31+12\",\"26/01/09,23:15:35+12\",0");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut message = String::new();
+    message.push_str("PPP_123_260109231531").unwrap();
+    let command = Command::SendAlarmSms { message, resends: 1 };
+    commands.try_send(command.clone()).unwrap();
+    let mut drv = driver(&sim);
+
+    let received = block_on(async {
+        let collect = async { [after_ready(&events).await, events.receive().await] };
+        match select(drv.run(commands.receiver(), events.sender()), collect).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    assert_eq!(
+        received,
+        [SimEvent::SmsSent(Ok(())), SimEvent::SmsDelivery { command, status: DeliveryStatus::Delivered }]
+    );
+}
+
+#[test]
+fn send_sms_fails_without_prompt() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("AT+CMGS=\"+79001234567\"").line("+CMS ERROR: 302");
+    let mut drv = driver(&sim);
+
+    assert_eq!(block_on(drv.send_sms(ADMIN, "PPP")), Err(Sim800Error::Cms(302)));
+}
+
+#[test]
+fn send_sms_reports_missing_prompt() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("AT+CMGS=\"+79001234567\"");
+    let mut drv = driver(&sim);
+
+    assert_eq!(block_on(drv.send_sms(ADMIN, "PPP")), Err(Sim800Error::NoPrompt));
+}
+
+#[test]
+fn dtmf_call_is_confirmed_by_remote() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("ATD+79001234567;")
+        .line("OK")
+        .delay(Duration::from_millis(100))
+        .line("+DTMF: *");
+    sim.expect("AT+VTS=\"1,0,7\"")
+        .line("OK")
+        .raw(b"\xff\xfe garbage\r\n")
+        .line("+DTMF: #");
+    sim.ok("AT+CHUP");
+    let mut drv = driver(&sim);
+
+    assert_eq!(block_on(drv.make_call_dtmf(ADMIN, "107")), Ok(()));
+
+    sim.assert_done();
+    assert_eq!(
+        sim.written_str(),
+        "ATD+79001234567;\r\n\r\nAT+VTS=\"1,0,7\"\r\nAT+CHUP\r\n"
+    );
+}
+
+#[test]
+fn busy_call_is_hung_up() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("ATD+79001234567;").line("OK").line("BUSY");
+    sim.ok("AT+CHUP");
+    let mut drv = driver(&sim);
+
+    assert_eq!(block_on(drv.make_call_dtmf(ADMIN, "107")), Err(Sim800Error::Busy));
+    sim.assert_done();
+}
+
+#[test]
+fn failed_dial_is_reported_at_once() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("ATD+79001234567;").line("BUSY");
+    sim.ok("AT+CHUP");
+    let mut drv = driver(&sim);
+
+    // Not a timeout of the wait for the remote, which counts as jamming.
+    assert_eq!(block_on(drv.make_call_dtmf(ADMIN, "107")), Err(Sim800Error::Busy));
+    sim.assert_done();
+}
+
+#[test]
+fn incoming_call_collects_dtmf_and_confirms() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.ok("ATA");
+    sim.expect("AT+VTS=\"*\"")
+        .line("OK")
+        .line("+DTMF: 3")
+        .delay(Duration::from_millis(20))
+        .line("+DTMF: 0")
+        .line("+DTMF: 5");
+    sim.ok("AT+VTS=\"#\"");
+    sim.ok("AT+CHUP");
+    let events = Channel::<NoopRawMutex, SimEvent, 8>::new();
+    let mut drv = driver(&sim);
+
+    block_on(drv.handle_incoming_call_flow(&events.sender()));
+
+    sim.assert_done();
+    let received: Vec<_> = core::iter::from_fn(|| events.try_receive().ok()).collect();
+    assert_eq!(
+        received,
+        [
+            SimEvent::DtmfReceived('3'),
+            SimEvent::DtmfReceived('0'),
+            SimEvent::DtmfReceived('5'),
+            SimEvent::CallEnded,
+        ]
+    );
+}
+
+#[test]
+fn run_reports_urcs_after_garbage() {
+    let sim = booting_sim();
+    sim.emit()
+        .raw(b"\x00\xc3\x28\r\n")
+        .line("RING")
+        .line("+CLIP: \"+79007654321\",145,\"\",0,\"\",0");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+
+    let received = block_on(async {
+        let collect = async { [after_ready(&events).await, events.receive().await] };
+        match select(drv.run(commands.receiver(), events.sender()), collect).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    let mut number = String::new();
+    number.push_str("+79007654321").unwrap();
+    assert_eq!(
+        received,
+        [SimEvent::ReceiveError(Sim800Error::Utf8), SimEvent::CallReceived { number }]
+    );
+}
+
+#[test]
+fn sms_text_mentioning_urcs_is_not_dispatched() {
+    let sim = booting_sim();
+    sim.emit()
+        .line("+CMT: \"Bee, Line\",\"\",\"26/01/09,23:15:31+12\"")
+        .raw(b"+CLIP: 777;\r\n");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+
+    let event = block_on(async {
+        match select(drv.run(commands.receiver(), events.sender()), after_ready(&events)).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(event) => event,
+        }
+    });
+
+    let mut number = String::new();
+    number.push_str("Bee, Line").unwrap();
+    let mut message = String::new();
+    message.push_str("+CLIP: 777;").unwrap();
+    assert_eq!(event, SimEvent::SmsReceived { number, message });
+}
+
+#[test]
+fn urcs_during_sms_prompt_are_not_lost() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("AT+CMGS=\"+79001234567\"")
+        .line("+CLIP: \"+79007654321\",145,\"\",0,\"\",0")
+        .raw(b"\r\n> ");
+    sim.expect("PPP").line("+CMGS: 3").line("OK");
+    let mut drv = driver(&sim);
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+
+    assert_eq!(block_on(drv.send_sms(ADMIN, "PPP")), Ok(()));
+    sim.assert_done();
+
+    // The queued call is forwarded once the driver loop has restarted the
+    // module, which is still running.
+    sim.ok("AT");
+    sim.expect("AT+CPOWD=1").line("NORMAL POWER DOWN").power_down();
+    script_power_on(&sim);
+    let event = block_on(async {
+        match select(drv.run(commands.receiver(), events.sender()), events.receive()).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(event) => event,
+        }
+    });
+    sim.assert_done();
+    assert_eq!(sim.power_cycles(), 1);
+    let mut number = String::new();
+    number.push_str("+79007654321").unwrap();
+    assert_eq!(event, SimEvent::CallReceived { number });
+}
+
+#[test]
+fn receiver_overrun_drops_the_damaged_line() {
+    let sim = booting_sim();
+    sim.emit()
+        .raw(b"\r\n+CLIP: \"+7900")
+        .overrun()
+        .raw(b"1234567\",145,\"\",0,\"\",0\r\n")
+        .line("+CLIP: \"+79007654321\",145,\"\",0,\"\",0");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+
+    let received = block_on(async {
+        let collect = async { [after_ready(&events).await, events.receive().await] };
+        match select(drv.run(commands.receiver(), events.sender()), collect).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    let mut number = String::new();
+    number.push_str("+79007654321").unwrap();
+    assert_eq!(
+        received,
+        [SimEvent::ReceiveError(Sim800Error::RxOverrun), SimEvent::CallReceived { number }]
+    );
+}
+
+#[test]
+fn supervisor_power_cycles_hung_modem() {
+    let sim = booting_sim();
+    sim.ok("AT+CSQ");
+    sim.ok("AT+CBC");
+    sim.emit().hang();
+    script_power_on(&sim);
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+    drv.set_ping_period(Duration::from_millis(100));
+
+    let event = block_on(async {
+        match select(drv.run(commands.receiver(), events.sender()), after_ready(&events)).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(event) => event,
+        }
+    });
+
+    sim.assert_done();
+    assert_eq!(event, SimEvent::ModemRestarted(RestartReason::Unresponsive(Sim800Error::Timeout)));
+    assert_eq!(sim.power_cycles(), 1);
+    assert!(sim.powered());
+    assert_eq!(drv.phone_book().get_first(), Some(ADMIN));
+}
+
+#[test]
+fn power_on_queries_state_of_autobaud_module() {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    // No RDY and no readiness URCs until the baud rate is known.
+    sim.on_power_on();
+    sim.ok("AT");
+    sim.expect("AT+CFUN?").line("+CFUN: 1").line("OK");
+    sim.expect("AT+CPIN?").line("+CPIN: READY").line("OK");
+    sim.expect("AT+CCALR?").line("+CCALR: 1").line("OK");
+    sim.ok("AT+CPMS?");
+    script_configure(&sim);
+    let mut drv = driver(&sim);
+
+    assert_eq!(block_on(drv.power_on()), Ok(()));
+    sim.assert_done();
+}
+
+#[test]
+fn power_on_finds_module_at_another_rate_and_fixes_it() {
+    let sim = ModemSim::new();
+    sim.powered_off().at_baud(Some(57600), 115200);
+    // Boot URCs come garbled at the wrong rate.
+    sim.on_power_on().line("RDY").line("+CFUN: 1").line("+CPIN: READY");
+    sim.ok("AT");
+    sim.expect("AT+CFUN?").line("+CFUN: 1").line("OK");
+    sim.expect("AT+CPIN?").line("+CPIN: READY").line("OK");
+    sim.expect("AT+CCALR?").line("+CCALR: 1").line("OK");
+    sim.ok("AT+CPMS?");
+    sim.expect("AT+IPR?").line("+IPR: 57600").line("OK");
+    sim.ok("AT+IPR=115200").baud(115200);
+    sim.ok("AT&W");
+    script_configure(&sim);
+    let mut drv = driver(&sim);
+    drv.set_baud_rate(Some(115200));
+
+    assert_eq!(block_on(drv.power_on()), Ok(()));
+    assert_eq!(sim.uart_baud(), Some(115200));
+    sim.assert_done();
+}
+
+#[test]
+fn power_on_reports_missing_sim() {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    sim.on_power_on().line("RDY").line("+CFUN: 1").line("+CPIN: NOT INSERTED");
+    let mut drv = driver(&sim);
+
+    assert_eq!(block_on(drv.power_on()), Err(PowerOnStage::Sim(SimFault::NotInserted)));
+    sim.assert_done();
+}
+
+#[test]
+fn power_on_enters_configured_pin() {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    sim.on_power_on().line("RDY").line("+CFUN: 1").line("+CPIN: SIM PIN");
+    sim.expect("AT+SPIC").line("+SPIC: 3,3,10,10").line("OK");
+    sim.expect("AT+CPIN=\"1234\"")
+        .line("OK")
+        .line("+CPIN: READY")
+        .line("Call Ready")
+        .line("SMS Ready");
+    script_configure(&sim);
+    let mut drv = driver(&sim);
+    drv.set_sim_pin(Some("1234"));
+
+    assert_eq!(block_on(drv.power_on()), Ok(()));
+    sim.assert_done();
+}
+
+#[test]
+fn power_on_keeps_last_pin_attempt() {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    sim.on_power_on().line("RDY").line("+CFUN: 1").line("+CPIN: SIM PIN");
+    sim.expect("AT+SPIC").line("+SPIC: 1,3,10,10").line("OK");
+    let mut drv = driver(&sim);
+    drv.set_sim_pin(Some("1234"));
+
+    assert_eq!(block_on(drv.power_on()), Err(PowerOnStage::Sim(SimFault::LastAttempt)));
+    sim.assert_done();
+}
+
+#[test]
+fn rejected_pin_is_not_retried() {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    sim.on_power_on().line("RDY").line("+CFUN: 1").line("+CPIN: SIM PIN");
+    sim.expect("AT+SPIC").line("+SPIC: 3,3,10,10").line("OK");
+    sim.expect("AT+CPIN=\"1234\"").line("+CME ERROR: 16");
+    let mut drv = driver(&sim);
+    drv.set_sim_pin(Some("1234"));
+
+    assert_eq!(block_on(drv.power_on()), Err(PowerOnStage::Sim(SimFault::PinRejected)));
+    sim.assert_done();
+}
+
+#[test]
+fn power_off_waits_for_normal_power_down() {
+    let sim = ModemSim::new();
+    sim.without_echo();
+    sim.expect("AT+CPOWD=1").line("NORMAL POWER DOWN").power_down();
+    let (tx, rx, mut power) = sim.split();
+    power.set_ttl(true);
+    let mut drv = Sim800Driver::new(tx, rx, power);
+
+    assert_eq!(block_on(drv.power_off()), Ok(()));
+    sim.assert_done();
+    assert!(!sim.powered());
+    assert!(!sim.ttl());
+}
+
+/// Module that boots without network coverage.
+fn searching_sim() -> ModemSim {
+    let sim = ModemSim::new();
+    sim.powered_off();
+    sim.on_power_on()
+        .line("RDY")
+        .line("+CFUN: 1")
+        .line("+CPIN: READY")
+        .line("Call Ready")
+        .line("SMS Ready");
+    script_configure_with_registration(&sim, "+CREG: 1,2");
+    sim
+}
+
+#[test]
+fn alarm_sms_waits_for_registration() {
+    let sim = searching_sim();
+    sim.emit().delay(Duration::from_millis(100)).line("+CREG: 1");
+    sim.expect(&format!("AT+CMGS=\"{ADMIN}\"")).raw(b"\r\n> ");
+    sim.expect("PPP_000").line("+CMGS: 4").line("OK");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+    let mut message = String::new();
+    message.push_str("PPP_000").unwrap();
+    commands.try_send(Command::SendAlarmSms { message, resends: 0 }).unwrap();
+
+    let received = block_on(async {
+        let collect = async {
+            [events.receive().await, events.receive().await, events.receive().await, events.receive().await]
+        };
+        match select(drv.run(commands.receiver(), events.sender()), collect).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    assert_eq!(
+        received,
+        [
+            SimEvent::NetworkStatus(RegistrationStatus::Searching),
+            SimEvent::ModemReady,
+            SimEvent::NetworkStatus(RegistrationStatus::Home),
+            SimEvent::SmsSent(Ok(())),
+        ]
+    );
+}
+
+#[test]
+fn prolonged_registration_loss_fails_deferred_commands() {
+    let sim = searching_sim();
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+    drv.set_registration_timeout(Duration::from_millis(200));
+    let mut dtmf = String::new();
+    dtmf.push_str("100").unwrap();
+    commands.try_send(Command::CallAlarmWithDtmf { dtmf }).unwrap();
+
+    let received = block_on(async {
+        let collect = async {
+            [events.receive().await, events.receive().await, events.receive().await, events.receive().await]
+        };
+        match select(drv.run(commands.receiver(), events.sender()), collect).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    assert_eq!(
+        received,
+        [
+            SimEvent::NetworkStatus(RegistrationStatus::Searching),
+            SimEvent::ModemReady,
+            SimEvent::NetworkLost,
+            SimEvent::CallExecuted(Err(Sim800Error::NotRegistered)),
+        ]
+    );
+}
+
+#[test]
+fn first_sim_is_provisioned() {
+    let sim = booting_sim();
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+    drv.set_provisioned_iccid(None);
+
+    let received = block_on(async {
+        let collect = async { [events.receive().await, events.receive().await, events.receive().await] };
+        match select(drv.run(commands.receiver(), events.sender()), collect).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    let mut iccid = String::new();
+    iccid.push_str(ICCID).unwrap();
+    assert_eq!(
+        received,
+        [
+            SimEvent::NetworkStatus(RegistrationStatus::Home),
+            SimEvent::SimProvisioned(iccid),
+            SimEvent::ModemReady,
+        ]
+    );
+}
+
+#[test]
+fn swapped_sim_is_reported() {
+    let sim = booting_sim();
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+    let mut expected = String::new();
+    expected.push_str("8970109999999999999").unwrap();
+    drv.set_provisioned_iccid(Some(expected.clone()));
+
+    let received = block_on(async {
+        let collect = async { [events.receive().await, events.receive().await, events.receive().await] };
+        match select(drv.run(commands.receiver(), events.sender()), collect).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    let mut found = String::new();
+    found.push_str(ICCID).unwrap();
+    assert_eq!(
+        received,
+        [
+            SimEvent::NetworkStatus(RegistrationStatus::Home),
+            SimEvent::SimChanged { expected, found },
+            SimEvent::ModemReady,
+        ]
+    );
+}
+
+#[test]
+fn idle_modem_sleeps_and_is_woken_for_commands() {
+    let sim = booting_sim();
+    sim.ok("AT+CSCLK=1");
+    sim.expect("AT+CCLK?").line(r#"+CCLK: "26/01/09,23:15:31+12""#).line("OK");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+    drv.set_sleep_idle(Some(Duration::from_millis(100)));
+
+    let (asleep, time, awake) = block_on(async {
+        let test = async {
+            assert_eq!(after_ready(&events).await, SimEvent::PowerState(ModemPowerState::Sleeping));
+            let asleep = sim.dtr();
+            commands.send(Command::UpdateTime).await;
+            (asleep, events.receive().await, events.receive().await)
+        };
+        match select(drv.run(commands.receiver(), events.sender()), test).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    assert!(asleep);
+    assert!(!sim.dtr());
+    assert!(matches!(time, SimEvent::TimeReceived(_)));
+    assert_eq!(awake, SimEvent::PowerState(ModemPowerState::Awake));
+    assert!(sim.written_str().ends_with("AT+CSCLK=1\r\nAT+CCLK?\r\n"));
+}
+
+#[test]
+fn ring_indicator_wakes_the_sleeping_modem() {
+    let sim = booting_sim();
+    sim.ok("AT+CSCLK=1");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+    drv.set_sleep_idle(Some(Duration::from_millis(100)));
+
+    let (asleep, awake) = block_on(async {
+        let test = async {
+            assert_eq!(after_ready(&events).await, SimEvent::PowerState(ModemPowerState::Sleeping));
+            let asleep = sim.dtr();
+            sim.ring();
+            (asleep, events.receive().await)
+        };
+        match select(drv.run(commands.receiver(), events.sender()), test).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    assert!(asleep);
+    assert!(!sim.dtr());
+    assert_eq!(awake, SimEvent::PowerState(ModemPowerState::Awake));
+}
+
+#[test]
+fn held_modem_is_woken_and_kept_awake() {
+    let sim = booting_sim();
+    sim.ok("AT+CSCLK=1");
+    let commands = Channel::<NoopRawMutex, Command, 4>::new();
+    let events = Channel::<NoopRawMutex, SimEvent, 4>::new();
+    let mut drv = driver(&sim);
+    drv.set_sleep_idle(Some(Duration::from_millis(100)));
+
+    let (awake, held_dtr, asleep) = block_on(async {
+        let test = async {
+            assert_eq!(after_ready(&events).await, SimEvent::PowerState(ModemPowerState::Sleeping));
+            let hold = sim.wake().hold().await;
+            let awake = events.receive().await;
+            // Well past the idle time.
+            Timer::after(Duration::from_millis(300)).await;
+            let held_dtr = sim.dtr();
+            drop(hold);
+            (awake, held_dtr, events.receive().await)
+        };
+        match select(drv.run(commands.receiver(), events.sender()), test).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(received) => received,
+        }
+    });
+
+    sim.assert_done();
+    assert_eq!(awake, SimEvent::PowerState(ModemPowerState::Awake));
+    assert!(!held_dtr);
+    assert_eq!(asleep, SimEvent::PowerState(ModemPowerState::Sleeping));
+}
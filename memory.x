@@ -1,6 +1,7 @@
 MEMORY
 {
   /* FLASH and RAM lengths for STM32F051R8 */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
+  /* The last two 1K pages hold the outbox and the settings (src/storage.rs) */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 62K
   RAM   : ORIGIN = 0x20000000, LENGTH = 8K
 }
\ No newline at end of file
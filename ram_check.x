@@ -0,0 +1,5 @@
+/* Linked after link.x (see .cargo/config.toml), once the statics are placed. */
+/* .data, .bss and .uninit must leave RAM for the stack, which grows down */
+/* from the end of RAM towards them. */
+_stack_reserve = 1K;
+ASSERT(__sheap + _stack_reserve <= ORIGIN(RAM) + LENGTH(RAM), "statics leave less than 1K of RAM for the stack")
@@ -1,33 +1,47 @@
 // /src/constants.rs
+pub use embassy800c_core::constants::*;
+
 pub const USE_SMS: bool = true;
 
 pub const LOW_INTRUSION_THRESHOLD: u16 = 1000;
 pub const HIGH_INTRUSION_THRESHOLD: u16 = 1500;
 
-pub const ALARMS_CHANNELS_AMOUNT: usize = 3;
-pub const ALARMS_STACK_DEPTH: usize = 3;
-pub const ALARMS_BUFFER_SIZE: usize = 256;
-pub const ALARMS_MESSAGE_STRING_LENGTH: usize = 3;
+/// Report codes outside the alarm digits (0-7 per channel).
+pub const LOW_SUPPLY_SIGNAL: &str = "888";
+pub const SUPPLY_RESTORED_SIGNAL: &str = "999";
+pub const LOW_BALANCE_SIGNAL: &str = "777";
+/// Sent once the network is back after suspected jamming, with its duration.
+pub const JAMMING_SIGNAL: &str = "666";
+
+/// USSD code answering with the prepaid balance.
+pub const BALANCE_USSD_CODE: &str = "*100#";
+/// Text of an incoming SMS asking for a balance check.
+pub const BALANCE_REQUEST_SMS: &str = "BALANCE";
+/// Text of an incoming SMS asking for the module and SIM identification.
+pub const STATUS_REQUEST_SMS: &str = "STATUS";
+/// Send the identification once the modem is ready after boot.
+pub const IDENTITY_ON_BOOT: bool = true;
+/// Balance in hundredths below which a low-balance report is sent, `None` to disable.
+pub const LOW_BALANCE_THRESHOLD: Option<i32> = None;
 
-pub const INIT_SIM800_DELAY_SECONDS: u32 = 6;
 pub const ALIVE_PERIOD_MINUTES: i32 = 120;
 pub const SYSTEM_MONITOR_PERIOD_HOURS: u32 = 12;
 
-pub const SMS_PREFIX: &str = "PPP";
-pub const SMS_DIVIDER: &str = "_";
-pub const ONLINE_SIGNAL: &str = "*";
-pub const CONFIRMATION_SIGNAL: &str = "#";
-pub const ERROR_SIGNAL: &str = "0";
-pub const DTMF_PACKET_LENGTH: usize = 3;
+/// Idle time after which the SIM800 sleeps (`AT+CSCLK=1`), `None` to keep it awake.
+pub const SIM800_SLEEP_IDLE_SECONDS: Option<u32> = Some(30);
 
-pub const MAX_PHONE_LENGTH: usize = 16;
+/// Rate USART2 starts at; the SIM800 is detected and fixed at it on power-on.
+/// At 38400 baud the `SIM800_RX_BUFFER_SIZE` DMA ring holds about 66 ms of
+/// received data, enough to ride out a flash erase; at 115200 it would be
+/// only 22 ms.
+pub const SIM800_BAUD_RATE: u32 = 38400;
 
-pub const SIM800_LINE_BUFFER_SIZE: usize = 64;
-pub const MAXIMUM_DTMF_BUFFER_SIZE: usize = 16;
-pub const MAXIMUM_SIM800_LINE_COUNT: usize = 8;
-pub const MAXIMUM_INCOMING_SMS_BUFFER_SIZE: usize = 8;
+/// Resends of an alarm SMS the network reported undelivered before its
+/// code is sent by an alarm call instead.
+pub const SMS_DELIVERY_RESENDS: u8 = 1;
 
-pub const SIM800_RX_BUFFER_SIZE: usize = 256;
+/// PIN of the SIM card, `None` if the PIN lock is disabled.
+pub const SIM_PIN: Option<&str> = None;
 
 /// The system clock frequency (in Hertz).
-pub const SYSCLK_HZ:    u32 = 16_000_000;
\ No newline at end of file
+pub const SYSCLK_HZ:    u32 = 16_000_000;
@@ -1,13 +1,20 @@
 // /src/hardware.rs
 use embassy_stm32::adc::{Adc, SampleTime};
-use embassy_stm32::gpio::{Level, Output, Speed};
+use embassy_stm32::flash::Flash;
+use embassy_stm32::exti::ExtiInput;
+use embassy_stm32::gpio::{Level, Output, Pull, Speed};
 use embassy_stm32::mode::Async;
 use embassy_stm32::peripherals::{ADC1, PA4, PA5, PA6};
 use embassy_stm32::rcc::{Hse, HseMode, Pll, PllMul, PllPreDiv, PllSource, Sysclk};
 use embassy_stm32::time::Hertz;
-use embassy_stm32::usart::{Config as UartConfig, Uart, UartRx, UartTx};
+use embassy_stm32::usart::{Config as UartConfig, RingBufferedUartRx, Uart, UartTx};
 use embassy_stm32::{adc, bind_interrupts, usart, Config, Peri};
-use defmt::info;
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy800c_core::cmux::{Cmux, CmuxReader, CmuxWriter, LinkState};
+use embassy800c_core::sim800::PowerControl;
+use crate::constants::{SIM800_BAUD_RATE, SIM800_RX_BUFFER_SIZE};
+use crate::storage::Storage;
 
 bind_interrupts!(pub struct Irqs {
     ADC1_COMP => adc::InterruptHandler<ADC1>;
@@ -17,9 +24,16 @@ bind_interrupts!(pub struct Irqs {
 
 // Correct Type Aliases for Async UART
 pub type Uart1 = Uart<'static, Async>;
-pub type Uart2Rx = UartRx<'static, Async>;
 pub type Uart2Tx = UartTx<'static, Async>;
 pub type Adc1 = Adc<'static, ADC1>;
+/// USART2 receiver filling a circular DMA buffer in the background, so URC
+/// bursts are not lost while the SIM800 task is busy. Reports
+/// `usart::Error::Overrun` if the buffer wraps before it is drained.
+pub type Sim800Rx = RingBufferedUartRx<'static>;
+/// GSM 07.10 multiplexer owning USART2; plain until the driver starts it.
+pub type Sim800Cmux = Cmux<CriticalSectionRawMutex, Uart2Tx>;
+pub type Sim800PortTx = CmuxWriter<'static, CriticalSectionRawMutex, Uart2Tx>;
+pub type Sim800PortRx = CmuxReader<'static, CriticalSectionRawMutex, Uart2Tx>;
 
 pub struct AnalogInputs {
     pub alarm_pullup: Output<'static>,
@@ -38,21 +52,67 @@ pub struct AlarmOutputs {
     pub alarm_out_1: Output<'static>,
     pub alarm_out_2: Output<'static>,
     pub alarm_out_3: Output<'static>,
+    /// Local siren relay, switched on while jamming is suspected.
+    pub siren: Output<'static>,
 }
 
 pub struct Sim800Control {
     pub sim800_enable: Output<'static>,
     pub sim800_ttl: Output<'static>,
+    pub sim800_dtr: Output<'static>,
+    pub cmux: &'static Sim800Cmux,
+}
+
+impl PowerControl for Sim800Control {
+    fn set_enable(&mut self, high: bool) {
+        self.sim800_enable.set_level(Level::from(high));
+    }
+
+    fn set_ttl(&mut self, high: bool) {
+        self.sim800_ttl.set_level(Level::from(high));
+    }
+
+    fn set_multiplexed(&mut self, on: bool) {
+        self.cmux.set_multiplexed(on);
+    }
+
+    fn is_multiplexed(&self) -> bool {
+        self.cmux.link_state() != LinkState::Plain
+    }
+
+    fn set_dtr(&mut self, high: bool) {
+        self.sim800_dtr.set_level(Level::from(high));
+        crate::SIM800_WAKE.set_asleep(high);
+    }
+
+    async fn wake_requested(&mut self) {
+        crate::SIM800_WAKE.requested().await;
+    }
+
+    fn keep_awake(&self) -> bool {
+        crate::SIM800_WAKE.is_held()
+    }
+
+    /// USART2 has a single rate register, so the receiver follows too.
+    fn set_baud_rate(&mut self, baud: u32) {
+        let res = self.cmux.try_with_tx(|tx| tx.set_baudrate(baud));
+        if !matches!(res, Some(Ok(()))) {
+            warn!("Could not set USART2 to {} baud", baud);
+        }
+    }
 }
 
 pub struct Board {
     pub analog_inputs: AnalogInputs, 
     pub alarm_outputs: AlarmOutputs,
     pub uart1: Uart1,
-    pub uart2_rx: Uart2Rx,
-    pub uart2_tx: Uart2Tx,
+    pub uart2_rx: Sim800Rx,
+    pub sim800_cmux: &'static Sim800Cmux,
+    /// SIM800 ring indicator, pulled low on incoming calls and SMS.
+    pub sim800_ri: ExtiInput<'static>,
     pub leds: Leds,
     pub sim800_control: Sim800Control,
+    pub storage: Storage,
 }
 
 pub fn init() -> Board {
@@ -78,8 +138,11 @@ pub fn init() -> Board {
     let alarm_out_1 = Output::new(p.PB3, Level::High, Speed::Low);
     let alarm_out_2 = Output::new(p.PB4, Level::High, Speed::Low);
     let alarm_out_3 = Output::new(p.PB5, Level::High, Speed::Low);
+    let siren = Output::new(p.PB6, Level::Low, Speed::Low);
     let sim800_ttl = Output::new(p.PC6, Level::Low, Speed::Low);
     let sim800_enable = Output::new(p.PC7, Level::Low, Speed::Low);
+    let sim800_dtr = Output::new(p.PC4, Level::Low, Speed::Low);
+    let sim800_ri = ExtiInput::new(p.PC5, p.EXTI5, Pull::Up);
 
     // 3. USART1
     let mut config_u1 = UartConfig::default();
@@ -94,7 +157,7 @@ pub fn init() -> Board {
 
     // 4. USART2
     let mut config_u2 = UartConfig::default();
-    config_u2.baudrate = 9600;
+    config_u2.baudrate = SIM800_BAUD_RATE;
     let (uart2_tx, uart2_rx) = Uart::new(
         p.USART2,
         p.PA3, p.PA2,
@@ -102,6 +165,9 @@ pub fn init() -> Board {
         p.DMA1_CH4, p.DMA1_CH5,
         config_u2,
     ).unwrap().split();
+    let sim800_rx_buf = cortex_m::singleton!(: [u8; SIM800_RX_BUFFER_SIZE] = [0; SIM800_RX_BUFFER_SIZE]).unwrap();
+    let uart2_rx = uart2_rx.into_ring_buffered(sim800_rx_buf);
+    let sim800_cmux = cortex_m::singleton!(: Sim800Cmux = Cmux::new(uart2_tx)).unwrap();
 
     // 5. ADC
     let mut adc = Adc::new(p.ADC1, Irqs);
@@ -118,6 +184,7 @@ pub fn init() -> Board {
         alarm_out_1,
         alarm_out_2,
         alarm_out_3,
+        siren,
     };
 
     let leds = Leds {
@@ -128,6 +195,8 @@ pub fn init() -> Board {
     let sim800_control = Sim800Control {
         sim800_enable,
         sim800_ttl,
+        sim800_dtr,
+        cmux: sim800_cmux,
     };
 
     Board {
@@ -135,8 +204,10 @@ pub fn init() -> Board {
         alarm_outputs,
         uart1,
         uart2_rx,
-        uart2_tx,
+        sim800_cmux,
+        sim800_ri,
         leds,
         sim800_control,
+        storage: Storage::new(Flash::new_blocking(p.FLASH)),
     }
 }
\ No newline at end of file
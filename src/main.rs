@@ -2,38 +2,51 @@
 #![no_std]
 #![no_main]
 
-use defmt::{info, warn};
+use defmt::{error, info, warn};
 use defmt_rtt as _;
 use embassy_stm32::adc::SampleTime;
 use panic_probe as _;
 
 use embassy_executor::Spawner;
-use embassy_futures::select::{select3, Either3};
+use embassy_futures::select::{select, select4, Either, Either4};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
 use embassy_time::{Duration, Instant, Timer};
 use heapless::String;
 
 mod constants;
 mod hardware;
-mod alarms_handler;
 mod rtc;
-mod sim800;
-mod gsm_time_converter;
-mod date_converter;
-mod phone_book;
-mod custom_strings;
+mod storage;
+
+use embassy800c_core::{custom_strings, date_converter};
+use embassy800c_core::alarms_handler::{AlarmStack, AlarmTracker};
+use embassy800c_core::cmux::{CMUX_AT_DLCI, CMUX_AUX_DLCI};
+use embassy800c_core::jamming_detector::{JammingChange, JammingDetector};
+use embassy800c_core::modem_wake::ModemWake;
+use embassy800c_core::outbox::{Outbox, Report};
+use embassy800c_core::signal_quality::SignalReport;
+use embassy800c_core::supply_monitor::{SupplyChange, SupplyMonitor};
+use embassy800c_core::sim800::{
+    Command, DeliveryStatus, ModemIdentity, Query, QueryChannel, Sim800Driver, Sim800Error, SimEvent, SimFault,
+};
 
 use crate::constants::*;
 use crate::hardware::{AnalogInputs, AlarmOutputs};
-use crate::alarms_handler::{AlarmStack, AlarmTracker};
 use crate::rtc::RtcControl;
-use crate::sim800::{Command, Sim800Driver, SimEvent};
+use crate::storage::Storage;
 
 // --- Global Signals/Channels ---
 static CMD_CHANNEL: Channel<CriticalSectionRawMutex, Command, 4> = Channel::new();
+/// Queries served on the auxiliary CMUX channel, see `send_query`.
+static QUERY_CHANNEL: Channel<CriticalSectionRawMutex, Query, 2> = Channel::new();
 static EVENT_CHANNEL: Channel<CriticalSectionRawMutex, SimEvent, 4> = Channel::new();
+/// Sleep and wake-up of the SIM800, driven by its driver for everyone else.
+pub static SIM800_WAKE: ModemWake<CriticalSectionRawMutex> = ModemWake::new();
+/// Number of blinks of the SIM fault pattern, 0 turns it off.
+static SIM_FAULT_LED: Signal<CriticalSectionRawMutex, u8> = Signal::new();
 
 // Shared State
 struct SystemState {
@@ -47,6 +60,10 @@ static STATE: Mutex<CriticalSectionRawMutex, SystemState> = Mutex::new(SystemSta
 });
 
 static RTC: Mutex<CriticalSectionRawMutex, Option<RtcControl>> = Mutex::new(None);
+static STORAGE: Mutex<CriticalSectionRawMutex, Option<Storage>> = Mutex::new(None);
+/// Alarm reports waiting to go out, fed to the SIM800 driver by `logic_task`.
+static OUTBOX: Mutex<CriticalSectionRawMutex, Outbox> = Mutex::new(Outbox::new());
+static SIM800_CMUX: Mutex<CriticalSectionRawMutex, Option<&'static hardware::Sim800Cmux>> = Mutex::new(None);
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
@@ -59,24 +76,66 @@ async fn main(spawner: Spawner) {
         *rtc_lock = Some(rtc_ctrl);
     }
 
+    let mut storage = board.storage;
+    storage.load_outbox(&mut *OUTBOX.lock().await);
+    *STORAGE.lock().await = Some(storage);
+    *SIM800_CMUX.lock().await = Some(board.sim800_cmux);
+
     info!("Starting Embassy800c...");
 
     // Spawn Tasks
-    spawner.spawn(sim800_task(board.uart2_tx, board.uart2_rx, board.sim800_control)).unwrap();
+    let (at_tx, at_rx) = board.sim800_cmux.port(CMUX_AT_DLCI);
+    let (aux_tx, aux_rx) = board.sim800_cmux.port(CMUX_AUX_DLCI);
+    spawner.spawn(cmux_task(board.sim800_cmux, board.uart2_rx)).unwrap();
+    spawner.spawn(sim800_task(at_tx, at_rx, board.sim800_control)).unwrap();
+    spawner.spawn(sim800_query_task(aux_tx, aux_rx)).unwrap();
+    spawner.spawn(ring_task(board.sim800_ri)).unwrap();
     spawner.spawn(adc_monitor_task(board.analog_inputs)).unwrap();
     spawner.spawn(logic_task(board.alarm_outputs)).unwrap();
     spawner.spawn(system_monitor_task()).unwrap();
+    spawner.spawn(led_task(board.leds)).unwrap();
+}
+
+#[embassy_executor::task]
+async fn cmux_task(cmux: &'static hardware::Sim800Cmux, rx: hardware::Sim800Rx) {
+    cmux.run(rx).await;
 }
 
 #[embassy_executor::task]
-async fn sim800_task(tx: hardware::Uart2Tx, rx: hardware::Uart2Rx, control: hardware::Sim800Control) {
+async fn sim800_task(tx: hardware::Sim800PortTx, rx: hardware::Sim800PortRx, control: hardware::Sim800Control) {
     let mut driver = Sim800Driver::new(tx, rx, control);
-    CMD_CHANNEL.send(Command::Init).await;
+    driver.set_sim_pin(SIM_PIN);
+    driver.set_cmux(true);
+    driver.set_baud_rate(Some(SIM800_BAUD_RATE));
+    driver.set_pdu_mode(true);
+    driver.set_sleep_idle(SIM800_SLEEP_IDLE_SECONDS.map(|secs| Duration::from_secs(secs as u64)));
+    if let Some(storage) = STORAGE.lock().await.as_mut() {
+        driver.set_provisioned_iccid(storage.provisioned_iccid());
+    }
     // Request time update immediately after initialization
     CMD_CHANNEL.send(Command::UpdateTime).await; 
     driver.run(CMD_CHANNEL.receiver(), EVENT_CHANNEL.sender()).await;
 }
 
+/// Wakes the SIM800 when its ring indicator announces a call or SMS, so it
+/// stays awake while the driver handles them. Requests do not queue up
+/// behind commands, however often the indicator fires.
+#[embassy_executor::task]
+async fn ring_task(mut ri: embassy_stm32::exti::ExtiInput<'static>) {
+    loop {
+        ri.wait_for_falling_edge().await;
+        info!("SIM800 ring indicator");
+        SIM800_WAKE.request();
+    }
+}
+
+/// Second AT channel, answering time and USSD queries during calls.
+#[embassy_executor::task]
+async fn sim800_query_task(tx: hardware::Sim800PortTx, rx: hardware::Sim800PortRx) {
+    let mut channel = QueryChannel::new(tx, rx, &SIM800_WAKE);
+    channel.run(QUERY_CHANNEL.receiver(), EVENT_CHANNEL.sender()).await;
+}
+
 #[embassy_executor::task]
 async fn adc_monitor_task(mut inputs: AnalogInputs) {
     let mut adc = inputs.adc;
@@ -104,11 +163,38 @@ async fn adc_monitor_task(mut inputs: AnalogInputs) {
 async fn logic_task(mut outputs: AlarmOutputs) {
     let mut watchdog_deadline: Option<Instant> = None;
     let mut dtmf_buffer = String::<DTMF_PACKET_LENGTH>::new();
+    let mut network_lost = false;
+    let mut signal: Option<SignalReport> = None;
+    let mut supply = SupplyMonitor::new();
+    let mut jamming = JammingDetector::new();
+    let mut boot_reported = false;
     
     // Sender logic timer
     let mut next_sender_tick = Instant::now() + Duration::from_secs(60);
+    // When the outbox changes collected so far are saved.
+    let mut save_at: Option<Instant> = None;
 
     loop {
+        // Save the outbox, then hand the next due report to the driver.
+        let (report_cmd, outbox_deadline) = {
+            let mut outbox = OUTBOX.lock().await;
+            let now = Instant::now();
+            if outbox.is_dirty() {
+                let at = *save_at.get_or_insert(now + Duration::from_secs(OUTBOX_SAVE_DELAY_SECONDS as u64));
+                // The flash write stalls everything; not while the SIM800 sends a report.
+                if now >= at && !outbox.is_sending() {
+                    save_at = None;
+                    persist_outbox(&mut outbox).await;
+                }
+            }
+            let cmd = outbox.dispatch(now);
+            let save = save_at.filter(|_| !outbox.is_sending());
+            (cmd, [outbox.deadline(), save].into_iter().flatten().min())
+        };
+        if let Some(cmd) = report_cmd {
+            CMD_CHANNEL.send(cmd).await;
+        }
+
         // Prepare Futures
         
         // 1. Watchdog Future
@@ -127,17 +213,28 @@ async fn logic_task(mut outputs: AlarmOutputs) {
         // 3. Event Future
         let event_fut = EVENT_CHANNEL.receive();
 
-        // Wait for any of the 3
-        match select3(event_fut, sender_fut, watchdog_fut).await {
+        // 4. Outbox Future
+        let outbox_fut = async {
+            match outbox_deadline {
+                Some(deadline) => Timer::at(deadline).await,
+                None => core::future::pending::<()>().await,
+            }
+        };
+
+        // Wait for any of the 4
+        match select4(event_fut, sender_fut, watchdog_fut, outbox_fut).await {
             // --- CASE 1: SIM800 EVENT RECEIVED ---
-            Either3::First(event) => {
+            Either4::First(event) => {
                 match event {
                     SimEvent::SmsReceived { message, .. } => {
-                        if let Some(alarm_str) = custom_strings::extract_before_delimiter(&message, ";") {
-                             if alarm_str.len() == ALARMS_MESSAGE_STRING_LENGTH {
-                                 play_received_alarms(&mut outputs, alarm_str).await;
-                                 watchdog_deadline = Some(Instant::now() + Duration::from_secs(255 * 60));
-                             }
+                        if message.trim().eq_ignore_ascii_case(BALANCE_REQUEST_SMS) {
+                            request_balance().await;
+                        } else if message.trim().eq_ignore_ascii_case(STATUS_REQUEST_SMS) {
+                            CMD_CHANNEL.send(Command::QueryIdentity).await;
+                        } else if let Some(alarm_str) = custom_strings::extract_before_delimiter(&message, ";")
+                            && alarm_str.len() == ALARMS_MESSAGE_STRING_LENGTH {
+                            play_received_alarms(&mut outputs, alarm_str).await;
+                            watchdog_deadline = Some(Instant::now() + Duration::from_secs(255 * 60));
                         }
                     },
                     SimEvent::DtmfReceived(c) => {
@@ -156,9 +253,39 @@ async fn logic_task(mut outputs: AlarmOutputs) {
                     SimEvent::CallReceived { number } => {
                         CMD_CHANNEL.send(Command::HandleIncomingCall { phone_number: number }).await;
                     },
-                    SimEvent::CallExecuted(success) => {
-                        if success { info!("Alarm Call Confirmed by Remote"); }
-                        else { warn!("Alarm Call Failed"); }
+                    SimEvent::CallExecuted(Ok(())) => {
+                        info!("Alarm Call Confirmed by Remote");
+                        report_sent().await;
+                    },
+                    SimEvent::CallExecuted(Err(e)) => {
+                        warn!("Alarm Call Failed: {:?}", e);
+                        let change = jamming.call_result(Err(e), Instant::now());
+                        handle_jamming(&mut outputs, change).await;
+                        report_failed(e).await;
+                    },
+                    SimEvent::SmsSent(Ok(())) => {
+                        info!("Alarm SMS sent");
+                        report_sent().await;
+                    },
+                    SimEvent::SmsSent(Err(e)) => {
+                        warn!("Alarm SMS Failed: {:?}", e);
+                        report_failed(e).await;
+                    },
+                    SimEvent::SmsDelivery { status: DeliveryStatus::Delivered, .. } => {
+                        info!("SMS delivered");
+                    },
+                    SimEvent::SmsDelivery { command: Command::SendAlarmSms { message, resends }, status } => {
+                        warn!("Alarm SMS not delivered: {:?}", status);
+                        // Alarm SMS come out of the outbox, so the text fits an entry again.
+                        match message.as_str().try_into() {
+                            Ok(text) if resends < SMS_DELIVERY_RESENDS => {
+                                resend_report(Report::Sms(text), resends + 1).await;
+                            }
+                            _ => escalate_undelivered(&message).await,
+                        }
+                    },
+                    SimEvent::SmsDelivery { status, .. } => {
+                        warn!("SMS not delivered: {:?}", status);
                     },
                     SimEvent::TimeReceived(time) => {
                          info!("Updating RTC...");
@@ -168,15 +295,101 @@ async fn logic_task(mut outputs: AlarmOutputs) {
                          }
                          info!("RTC was updated.");
                     }
+                    SimEvent::ReceiveError(e) => {
+                        warn!("SIM800 receive error: {:?}", e);
+                    }
+                    SimEvent::ModemReady => {
+                        info!("SIM800 ready");
+                        SIM_FAULT_LED.signal(0);
+                        if IDENTITY_ON_BOOT && !boot_reported {
+                            boot_reported = true;
+                            CMD_CHANNEL.send(Command::QueryIdentity).await;
+                        }
+                    }
+                    SimEvent::PowerOnFailed(stage) => {
+                        error!("SIM800 power on failed at {:?}", stage);
+                    }
+                    SimEvent::SimFault(fault) => {
+                        error!("SIM card unusable: {:?}", fault);
+                        SIM_FAULT_LED.signal(sim_fault_blinks(fault));
+                    }
+                    SimEvent::NetworkStatus(status) => {
+                        info!("Network registration: {:?}", status);
+                        let change = jamming.registration(status, Instant::now());
+                        handle_jamming(&mut outputs, change).await;
+                        // Report on the next tick once the outage is over.
+                        if status.is_registered() && network_lost {
+                            network_lost = false;
+                            STATE.lock().await.alive_countdown = 0;
+                        }
+                    }
+                    SimEvent::SignalQuality(report) => {
+                        info!("Signal quality: {:?}", report);
+                        let change = jamming.signal(report.quality, Instant::now());
+                        handle_jamming(&mut outputs, change).await;
+                        signal = Some(report);
+                    }
+                    SimEvent::SupplyVoltage(millivolts) => {
+                        if let Some(change) = supply.update(millivolts) {
+                            warn!("Supply {:?} at {} mV", change, millivolts);
+                            let code = match change {
+                                SupplyChange::Low => LOW_SUPPLY_SIGNAL,
+                                SupplyChange::Restored => SUPPLY_RESTORED_SIGNAL,
+                            };
+                            send_status_report(code, millivolts as i32).await;
+                        }
+                    }
+                    SimEvent::SimProvisioned(iccid) => {
+                        if let Some(storage) = STORAGE.lock().await.as_mut() {
+                            storage.store_iccid(&iccid);
+                        }
+                    }
+                    SimEvent::SimChanged { expected, found } => {
+                        error!("SIM swapped: expected {}, found {}", expected.as_str(), found.as_str());
+                        // Goes to the phone book of the new SIM, if it has one.
+                        let mut msg = String::<SMS_MESSAGE_LENGTH>::new();
+                        use core::fmt::Write;
+                        let _ = write!(msg, "{}{}TAMPER{}{}{}{}", SMS_PREFIX, SMS_DIVIDER, SMS_DIVIDER, expected.as_str(), SMS_DIVIDER, found.as_str());
+                        queue_report(Report::Sms(msg)).await;
+                    }
+                    SimEvent::PowerState(state) => {
+                        info!("SIM800 power state: {:?}", state);
+                    }
+                    SimEvent::Identity(identity) => {
+                        send_identity(&identity).await;
+                    }
+                    SimEvent::UssdResponse(Ok(answer)) => {
+                        info!("USSD answer: {}", answer.as_str());
+                        if let Some(threshold) = LOW_BALANCE_THRESHOLD
+                            && let Some(balance) = custom_strings::parse_amount(&answer)
+                            && balance < threshold {
+                            warn!("Balance low: {}", balance);
+                            send_status_report(LOW_BALANCE_SIGNAL, balance).await;
+                        }
+                        let mut message = String::new();
+                        let _ = message.push_str(&answer);
+                        queue_report(Report::Sms(message)).await;
+                    }
+                    SimEvent::UssdResponse(Err(e)) => {
+                        warn!("USSD request failed: {:?}", e);
+                    }
+                    SimEvent::NetworkLost => {
+                        error!("SIM800 lost the network");
+                        network_lost = true;
+                    }
+                    SimEvent::ModemRestarted(reason) => {
+                        warn!("SIM800 was restarted: {:?}", reason);
+                        send_query(Query::UpdateTime).await;
+                    }
                 }
             },
 
             // --- CASE 2: SENDER LOGIC TICK (Every 60s) ---
-            Either3::Second(_) => {
+            Either4::Second(_) => {
                 next_sender_tick += Duration::from_secs(60);
                 
                 let mut pending_dtmf: Option<String<DTMF_PACKET_LENGTH>> = None;
-                let mut pending_sms: Option<String<SIM800_LINE_BUFFER_SIZE>> = None;
+                let mut pending_sms: Option<String<SMS_MESSAGE_LENGTH>> = None;
                 let mut is_sms = false;
 
                 // Scope lock
@@ -191,22 +404,15 @@ async fn logic_task(mut outputs: AlarmOutputs) {
                         state.alive_countdown = ALIVE_PERIOD_MINUTES + 1;
 
                         if USE_SMS {
-                             let time_buf = {
-                                 let rtc = RTC.lock().await;
-                                 // Use 'ref' instead of 'ref mut' because get_time is immutable
-                                 if let Some(ref rtc_ctrl) = *rtc {
-                                     let t = rtc_ctrl.get_time();
-                                     crate::date_converter::format_gsm_time(&t)
-                                 } else {
-                                     crate::date_converter::format_gsm_time(&crate::rtc::GsmTime { 
-                                         year:0, month:0, day:0, hour:0, minute:0, second:0 
-                                     })
-                                 }
-                             };
-
-                             let mut msg = String::<SIM800_LINE_BUFFER_SIZE>::new();
+                             let time_buf = timestamp().await;
+
+                             let mut msg = String::<SMS_MESSAGE_LENGTH>::new();
                              use core::fmt::Write;
                              let _ = write!(msg, "{}{}{}{}{}", SMS_PREFIX, SMS_DIVIDER, str_stack, SMS_DIVIDER, time_buf.as_str());
+                             // Heartbeats carry the coverage: current/min/avg/max dBm.
+                             if tick && let Some(report) = signal {
+                                 let _ = write!(msg, "{}{}", SMS_DIVIDER, report);
+                             }
                              pending_sms = Some(msg);
                              is_sms = true;
                         } else {
@@ -220,27 +426,174 @@ async fn logic_task(mut outputs: AlarmOutputs) {
 
                 if is_sms {
                     if let Some(msg) = pending_sms {
-                        CMD_CHANNEL.send(Command::SendAlarmSms { message: msg }).await;
+                        queue_report(Report::Sms(msg)).await;
                     }
                 } else if let Some(dtmf) = pending_dtmf {
                     info!("Sending Alarm Report: {}", dtmf.as_str());
-                    CMD_CHANNEL.send(Command::CallAlarmWithDtmf { dtmf }).await;
+                    queue_report(Report::Call(dtmf)).await;
                 }
             },
 
             // --- CASE 3: WATCHDOG TIMEOUT ---
-            Either3::Third(_) => {
+            Either4::Third(_) => {
                 info!("Watchdog 4.5h expired. Resetting relays to Low.");
                 outputs.alarm_out_1.set_low();
                 outputs.alarm_out_2.set_low();
                 outputs.alarm_out_3.set_low();
                 watchdog_deadline = None;
             }
+
+            // --- CASE 4: OUTBOX REPORT DUE (dispatched at the top of the loop) ---
+            Either4::Fourth(_) => {}
+        }
+    }
+}
+
+/// Current RTC time as used in report SMS.
+async fn timestamp() -> date_converter::TimeBuffer {
+    let rtc = RTC.lock().await;
+    // Use 'ref' instead of 'ref mut' because get_time is immutable
+    if let Some(ref rtc_ctrl) = *rtc {
+        let t = rtc_ctrl.get_time();
+        date_converter::format_gsm_time(&t)
+    } else {
+        date_converter::format_gsm_time(&crate::rtc::GsmTime { 
+            year:0, month:0, day:0, hour:0, minute:0, second:0 
+        })
+    }
+}
+
+/// Sends a status code through the alarm path: `PPP_<code>_<time>_<value>`
+/// by SMS, or the bare code by DTMF.
+async fn send_status_report(code: &str, value: i32) {
+    if USE_SMS {
+        let time_buf = timestamp().await;
+        let mut msg = String::<SMS_MESSAGE_LENGTH>::new();
+        use core::fmt::Write;
+        let _ = write!(msg, "{}{}{}{}{}{}{}", SMS_PREFIX, SMS_DIVIDER, code, SMS_DIVIDER, time_buf.as_str(), SMS_DIVIDER, value);
+        queue_report(Report::Sms(msg)).await;
+    } else {
+        let mut dtmf = String::<DTMF_PACKET_LENGTH>::new();
+        let _ = dtmf.push_str(code);
+        queue_report(Report::Call(dtmf)).await;
+    }
+}
+
+/// Sounds the local siren while jamming is suspected; the episode is
+/// reported once the network is back, as nothing gets through before.
+async fn handle_jamming(outputs: &mut AlarmOutputs, change: Option<JammingChange>) {
+    match change {
+        Some(JammingChange::Suspected) => {
+            error!("Possible GSM jamming");
+            outputs.siren.set_high();
+        }
+        Some(JammingChange::Ended(episode)) => {
+            warn!("Jamming episode ended: {:?}", episode);
+            outputs.siren.set_low();
+            send_status_report(JAMMING_SIGNAL, episode.duration_secs as i32).await;
+        }
+        None => {}
+    }
+}
+
+/// Queues an alarm report; `logic_task` sends it once the ones before it
+/// went out.
+async fn queue_report(report: Report) {
+    let mut outbox = OUTBOX.lock().await;
+    outbox.push(report, Instant::now());
+}
+
+/// Queues an alarm report again that went out but was not delivered.
+async fn resend_report(report: Report, resends: u8) {
+    let mut outbox = OUTBOX.lock().await;
+    outbox.resend(report, resends, Instant::now());
+}
+
+/// The report being sent went out.
+async fn report_sent() {
+    let mut outbox = OUTBOX.lock().await;
+    outbox.sent();
+}
+
+/// Retries a failed alarm report with backoff, unless retrying cannot help
+/// (no SIM, no recipient).
+async fn report_failed(e: Sim800Error) {
+    let retry = if e.is_sim_failure() {
+        error!("SIM card problem, not retrying: {:?}", e);
+        false
+    } else if e == Sim800Error::NoRecipient {
+        error!("No recipient in the SIM phone book");
+        false
+    } else {
+        true
+    };
+    let mut outbox = OUTBOX.lock().await;
+    if let Some(report) = outbox.failed(Instant::now(), retry) {
+        error!("Giving up on alarm report {:?}", report);
+    }
+}
+
+/// Saves the outbox to flash while reports are backed up, so that they
+/// survive a reset; reports that go out first time cost no flash writes.
+/// Called by `logic_task` once changes have collected for
+/// `OUTBOX_SAVE_DELAY_SECONDS`, so that a burst of them costs one save.
+async fn persist_outbox(outbox: &mut Outbox) {
+    if outbox.take_dirty() && let Some(storage) = STORAGE.lock().await.as_mut() {
+        storage.store_outbox(outbox);
+    }
+}
+
+/// Falls back to an alarm call for an SMS report that did not get through,
+/// carrying its code (`PPP_<code>_...`) by DTMF.
+async fn escalate_undelivered(message: &str) {
+    let code = message.split(SMS_DIVIDER).nth(1).filter(|code| {
+        code.len() == DTMF_PACKET_LENGTH && code.bytes().all(|b| b.is_ascii_digit())
+    });
+    let Some(code) = code else {
+        error!("Undelivered SMS has no alarm code, giving up: {}", message);
+        return;
+    };
+    error!("Alarm SMS undeliverable, calling with DTMF {}", code);
+    let mut dtmf = String::<DTMF_PACKET_LENGTH>::new();
+    let _ = dtmf.push_str(code);
+    queue_report(Report::Call(dtmf)).await;
+}
+
+/// Blink pattern on led4: 1 - no SIM, 2 - PIN locked, 3 - PUK blocked.
+fn sim_fault_blinks(fault: SimFault) -> u8 {
+    match fault {
+        SimFault::NotInserted | SimFault::NotReady => 1,
+        SimFault::PinRequired | SimFault::PinRejected | SimFault::LastAttempt => 2,
+        SimFault::PukRequired => 3,
+    }
+}
+
+#[embassy_executor::task]
+async fn led_task(mut leds: hardware::Leds) {
+    let mut blinks = 0;
+    loop {
+        if blinks == 0 {
+            leds.led4.set_low();
+            blinks = SIM_FAULT_LED.wait().await;
+            continue;
+        }
+        for _ in 0..blinks {
+            leds.led4.set_high();
+            Timer::after(Duration::from_millis(200)).await;
+            leds.led4.set_low();
+            Timer::after(Duration::from_millis(300)).await;
+        }
+        if let Either::First(next) = select(SIM_FAULT_LED.wait(), Timer::after(Duration::from_secs(2))).await {
+            blinks = next;
         }
     }
 }
 
 async fn play_received_alarms(outputs: &mut AlarmOutputs, alarm_str: &str) {
+    if [LOW_SUPPLY_SIGNAL, SUPPLY_RESTORED_SIGNAL, LOW_BALANCE_SIGNAL, JAMMING_SIGNAL].contains(&alarm_str) {
+        warn!("Remote status report: {}", alarm_str);
+        return;
+    }
     info!("Playing received alarms: {}", alarm_str);
     
     let mut alarm_chars = ['\0'; ALARMS_MESSAGE_STRING_LENGTH];
@@ -263,10 +616,43 @@ async fn play_received_alarms(outputs: &mut AlarmOutputs, alarm_str: &str) {
     info!("Alarm playback finished. Relays holding last state.");
 }
 
+/// Sends the module (`PPP_ID_<imei>_<revision>`) and SIM
+/// (`PPP_SIM_<iccid>_<imsi>`) identification to the admin; both do not fit
+/// into one message.
+async fn send_identity(identity: &ModemIdentity) {
+    use core::fmt::Write;
+    let mut module = String::<SMS_MESSAGE_LENGTH>::new();
+    let _ = write!(module, "{}{}ID{}{}{}{}", SMS_PREFIX, SMS_DIVIDER, SMS_DIVIDER, identity.imei.as_str(), SMS_DIVIDER, identity.revision.as_str());
+    queue_report(Report::Sms(module)).await;
+
+    let mut sim = String::<SMS_MESSAGE_LENGTH>::new();
+    let _ = write!(sim, "{}{}SIM{}{}{}{}", SMS_PREFIX, SMS_DIVIDER, SMS_DIVIDER, identity.iccid.as_str(), SMS_DIVIDER, identity.imsi.as_str());
+    queue_report(Report::Sms(sim)).await;
+}
+
+/// Asks the network for the SIM balance; the answer is forwarded to the admin.
+async fn request_balance() {
+    let mut code = String::new();
+    let _ = code.push_str(BALANCE_USSD_CODE);
+    send_query(Query::Ussd { code }).await;
+}
+
+/// Sends a query to the auxiliary CMUX channel while it is open, so that a
+/// call on the AT channel does not hold it up, else to the driver.
+async fn send_query(query: Query) {
+    let aux_open = SIM800_CMUX.lock().await.is_some_and(|cmux| cmux.is_open(CMUX_AUX_DLCI));
+    if aux_open {
+        QUERY_CHANNEL.send(query).await;
+    } else {
+        CMD_CHANNEL.send(query.into()).await;
+    }
+}
+
 #[embassy_executor::task]
 async fn system_monitor_task() {
     loop {
         Timer::after(Duration::from_secs(SYSTEM_MONITOR_PERIOD_HOURS as u64 * 3600)).await;
-        CMD_CHANNEL.send(Command::UpdateTime).await;
+        send_query(Query::UpdateTime).await;
+        request_balance().await;
     }
 }
\ No newline at end of file
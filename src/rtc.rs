@@ -1,15 +1,7 @@
 // /src/rtc.rs
 use embassy_stm32::pac::{PWR, RCC, RTC};
 
-#[derive(Debug, Clone, Copy, defmt::Format)]
-pub struct GsmTime{
-    pub year: u8,
-    pub month: u8,
-    pub day: u8,
-    pub hour: u8,
-    pub minute: u8,
-    pub second: u8,
-}
+pub use embassy800c_core::gsm_time_converter::GsmTime;
 
 /// RTC control using LSE/LSI as clock source.
 pub struct RtcControl {
@@ -0,0 +1,100 @@
+// /src/storage.rs
+//! Settings kept in the last flash page and backed-up alarm reports in the
+//! page before, both left out of the program's FLASH region by `memory.x`.
+
+use defmt::{info, warn};
+use embassy_stm32::flash::{Blocking, Flash};
+use embassy_time::Instant;
+use heapless::String;
+
+use embassy800c_core::outbox::Outbox;
+
+use crate::constants::ICCID_LENGTH;
+
+/// Offset of the settings page from the start of flash.
+const SETTINGS_OFFSET: u32 = 63 * 1024;
+const SETTINGS_PAGE_SIZE: u32 = 1024;
+const SETTINGS_MAGIC: [u8; 4] = *b"E8C1";
+
+/// Record layout: magic, ICCID length, padding, ICCID digits.
+const RECORD_SIZE: usize = 8 + ICCID_LENGTH;
+
+const OUTBOX_OFFSET: u32 = 62 * 1024;
+const OUTBOX_MAGIC: [u8; 4] = *b"E8O2";
+/// Record layout: magic, saved outbox.
+const OUTBOX_DATA_OFFSET: u32 = OUTBOX_OFFSET + 4;
+const _: () = assert!(4 + Outbox::RECORD_SIZE <= SETTINGS_PAGE_SIZE as usize);
+
+pub struct Storage {
+    flash: Flash<'static, Blocking>,
+}
+
+impl Storage {
+    pub fn new(flash: Flash<'static, Blocking>) -> Self {
+        Self { flash }
+    }
+
+    /// ICCID of the SIM the unit was set up with, `None` on a fresh unit.
+    pub fn provisioned_iccid(&mut self) -> Option<String<ICCID_LENGTH>> {
+        let mut record = [0u8; RECORD_SIZE];
+        self.flash.blocking_read(SETTINGS_OFFSET, &mut record).ok()?;
+        if record[..4] != SETTINGS_MAGIC {
+            return None;
+        }
+        let len = (record[4] as usize).min(ICCID_LENGTH);
+        let iccid = core::str::from_utf8(&record[8..8 + len]).ok()?;
+        iccid.try_into().ok()
+    }
+
+    pub fn store_iccid(&mut self, iccid: &str) {
+        let mut record = [0xFFu8; RECORD_SIZE];
+        let len = iccid.len().min(ICCID_LENGTH);
+        record[..4].copy_from_slice(&SETTINGS_MAGIC);
+        record[4] = len as u8;
+        record[8..8 + len].copy_from_slice(&iccid.as_bytes()[..len]);
+
+        let res = self.flash
+            .blocking_erase(SETTINGS_OFFSET, SETTINGS_OFFSET + SETTINGS_PAGE_SIZE)
+            .and_then(|_| self.flash.blocking_write(SETTINGS_OFFSET, &record));
+        match res {
+            Ok(()) => info!("Provisioned SIM stored"),
+            Err(e) => warn!("Storing settings failed: {:?}", e),
+        }
+    }
+
+    /// Restores the reports that were backed up before the reset.
+    pub fn load_outbox(&mut self, outbox: &mut Outbox) {
+        let mut magic = [0u8; 4];
+        if self.flash.blocking_read(OUTBOX_OFFSET, &mut magic).is_err() || magic != OUTBOX_MAGIC {
+            return;
+        }
+        let flash = &mut self.flash;
+        let res = outbox.load(|offset, buf| flash.blocking_read(OUTBOX_DATA_OFFSET + offset as u32, buf), Instant::now());
+        if let Err(e) = res {
+            warn!("Reading the outbox failed: {:?}", e);
+        }
+        if !outbox.is_empty() {
+            warn!("{} alarm reports restored", outbox.len());
+        }
+    }
+
+    /// Saves the outbox, empty or not, over the previous copy. It is written
+    /// an entry at a time and the magic last, so that a reset halfway
+    /// leaves no copy rather than a damaged one.
+    ///
+    /// Stalls the executor, and the CPU, which runs from the flash being
+    /// written: the page erase takes up to 40 ms and the 138 half-words of a
+    /// full outbox up to 10 ms, so 50 ms at worst. The SIM800 receive ring
+    /// keeps filling by DMA and holds about 66 ms at `SIM800_BAUD_RATE`;
+    /// `logic_task` saves only while no report is being sent.
+    pub fn store_outbox(&mut self, outbox: &mut Outbox) {
+        let flash = &mut self.flash;
+        let res = flash
+            .blocking_erase(OUTBOX_OFFSET, OUTBOX_OFFSET + SETTINGS_PAGE_SIZE)
+            .and_then(|_| outbox.save(|offset, bytes| flash.blocking_write(OUTBOX_DATA_OFFSET + offset as u32, bytes)))
+            .and_then(|_| flash.blocking_write(OUTBOX_OFFSET, &OUTBOX_MAGIC));
+        if let Err(e) = res {
+            warn!("Storing the outbox failed: {:?}", e);
+        }
+    }
+}